@@ -1,36 +1,105 @@
+use std::io::BufRead;
+use std::path::Path;
 use std::{cmp::Reverse, collections::BinaryHeap};
 
-use crate::{read_file_to_string, solve, BoxResult};
+use crate::{read_file_to_buffer, read_file_to_string, solve, BoxResult};
 
-fn part_one(input: &str) -> BoxResult<usize> {
-    let mut max_calories: usize = 0;
-    let mut caloriers_per_person: usize = 0;
-    input.lines().for_each(|line| match line.parse::<usize>() {
-        Ok(n) => caloriers_per_person += n,
-        Err(_) => {
-            if max_calories < caloriers_per_person {
-                max_calories = caloriers_per_person
-            }
-            caloriers_per_person = 0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Elf {
+    pub index: usize,
+    pub items: Vec<usize>,
+}
+
+impl Elf {
+    pub fn calories(&self) -> usize {
+        self.items.iter().sum()
+    }
+}
+
+// Calories, then index reversed so that earlier elves rank higher, then items.
+type Ranked = (usize, Reverse<usize>, Vec<usize>);
+
+// Keeps only the `n` elves carrying the most calories seen so far. On equal
+// calories the elf that came first is kept.
+struct TopElves {
+    n: usize,
+    heap: BinaryHeap<Reverse<Ranked>>,
+}
+
+impl TopElves {
+    fn new(n: usize) -> Self {
+        TopElves {
+            n,
+            heap: BinaryHeap::with_capacity(n + 1),
+        }
+    }
+
+    fn push(&mut self, elf: Elf) {
+        self.heap
+            .push(Reverse((elf.calories(), Reverse(elf.index), elf.items)));
+        if self.heap.len() > self.n {
+            self.heap.pop();
         }
-    });
-    Ok(max_calories)
+    }
+
+    fn into_sorted_vec(self) -> Vec<Elf> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((_, Reverse(index), items))| Elf { index, items })
+            .collect()
+    }
 }
 
-fn part_two(input: &str) -> BoxResult<usize> {
-    let mut three_max_calories = BinaryHeap::with_capacity(4);
-    let mut caloriers_per_person: usize = 0;
-    input.lines().for_each(|line| match line.parse::<usize>() {
-        Ok(n) => caloriers_per_person += n,
-        Err(_) => {
-            three_max_calories.push(Reverse(caloriers_per_person));
-            if three_max_calories.len() > 3 {
-                three_max_calories.pop();
+// Reads the elves one at a time, so only the current elf and the top `n` are
+// held in memory. The last elf does not need to be followed by a blank line.
+pub fn top_elves<R: BufRead>(reader: R, n: usize) -> BoxResult<Vec<Elf>> {
+    let mut top = TopElves::new(n);
+    let mut current = Elf {
+        index: 0,
+        items: vec![],
+    };
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            if !current.items.is_empty() {
+                let index = current.index + 1;
+                top.push(std::mem::replace(
+                    &mut current,
+                    Elf {
+                        index,
+                        items: vec![],
+                    },
+                ));
             }
-            caloriers_per_person = 0
+        } else {
+            current.items.push(line.trim().parse::<usize>()?);
         }
-    });
-    Ok(three_max_calories.into_iter().map(|rev| rev.0).sum())
+    }
+    if !current.items.is_empty() {
+        top.push(current);
+    }
+
+    Ok(top.into_sorted_vec())
+}
+
+pub fn top_elves_in_file<P: AsRef<Path>>(path: P, n: usize) -> BoxResult<Vec<Elf>> {
+    top_elves(read_file_to_buffer(path)?, n)
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    Ok(top_elves(input.as_bytes(), 1)?
+        .iter()
+        .map(|elf| elf.calories())
+        .sum())
+}
+
+fn part_two(input: &str) -> BoxResult<usize> {
+    Ok(top_elves(input.as_bytes(), 3)?
+        .iter()
+        .map(|elf| elf.calories())
+        .sum())
 }
 
 pub fn run() {
@@ -54,4 +123,24 @@ mod tests {
         let input = &read_file_to_string("./examples/day01.txt");
         assert_eq!(part_two(input).ok(), Some(45000));
     }
+
+    #[test]
+    fn test_top_elves() {
+        let elves = top_elves_in_file("./examples/day01.txt", 2).unwrap();
+        assert_eq!(
+            elves,
+            vec![
+                Elf {
+                    index: 3,
+                    items: vec![7000, 8000, 9000]
+                },
+                Elf {
+                    index: 2,
+                    items: vec![5000, 6000]
+                },
+            ]
+        );
+        assert_eq!(top_elves("1\n\n\n2\n".as_bytes(), 5).unwrap().len(), 2);
+        assert!(top_elves("1\nx\n".as_bytes(), 1).is_err());
+    }
 }
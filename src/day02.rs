@@ -1,48 +1,239 @@
-use crate::{read_file_to_string, solve, BoxResult};
+use std::str::FromStr;
 
-const ROCK: usize = 1;
-const PAPER: usize = 2;
-const SCISSORS: usize = 3;
-const WIN: usize = 6;
-const DRAW: usize = 3;
-const LOSE: usize = 0;
+use crate::{read_file_to_string, solve, AOCError, BoxResult};
 
-fn part_one(input: &str) -> BoxResult<usize> {
-    let res = input.lines().fold(0usize, |sum, line| {
-        sum + match line {
-            "A X" => ROCK + DRAW,
-            "A Y" => PAPER + WIN,
-            "A Z" => SCISSORS + LOSE,
-            "B X" => ROCK + LOSE,
-            "B Y" => PAPER + DRAW,
-            "B Z" => SCISSORS + WIN,
-            "C X" => ROCK + WIN,
-            "C Y" => PAPER + LOSE,
-            "C Z" => SCISSORS + DRAW,
-            _ => 0,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Rock,
+    Paper,
+    Scissors,
+}
+
+impl Shape {
+    // Each shape beats the one before it, wrapping around.
+    const ALL: [Shape; 3] = [Shape::Rock, Shape::Paper, Shape::Scissors];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn beats(self) -> Shape {
+        Shape::ALL[(self.index() + 2) % 3]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Lose,
+    Draw,
+    Win,
+}
+
+impl Outcome {
+    pub fn score(self) -> usize {
+        match self {
+            Outcome::Lose => 0,
+            Outcome::Draw => 3,
+            Outcome::Win => 6,
         }
-    });
-    Ok(res)
+    }
 }
 
-fn part_two(input: &str) -> BoxResult<usize> {
-    let res = input.lines().fold(0usize, |sum, line| {
-        sum + match line {
-            "A X" => SCISSORS + LOSE,
-            "A Y" => ROCK + DRAW,
-            "A Z" => PAPER + WIN,
-            "B X" => ROCK + LOSE,
-            "B Y" => PAPER + DRAW,
-            "B Z" => SCISSORS + WIN,
-            "C X" => PAPER + LOSE,
-            "C Y" => SCISSORS + DRAW,
-            "C Z" => ROCK + WIN,
-            _ => 0,
+impl FromStr for Outcome {
+    type Err = AOCError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "X" => Ok(Outcome::Lose),
+            "Y" => Ok(Outcome::Draw),
+            "Z" => Ok(Outcome::Win),
+            _ => Err(AOCError),
         }
-    });
+    }
+}
+
+// Position of a shape in the list of a `Game`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShapeId(usize);
+
+// How the second column of the strategy guide is read, given the shape
+// played by the opponent.
+pub trait Strategy {
+    fn response(&self, game: &Game, opponent: ShapeId, column: &str) -> Result<ShapeId, AOCError>;
+}
+
+pub struct ColumnIsShape;
+
+impl Strategy for ColumnIsShape {
+    fn response(&self, game: &Game, _opponent: ShapeId, column: &str) -> Result<ShapeId, AOCError> {
+        game.response_shape(column)
+    }
+}
+
+pub struct ColumnIsOutcome;
+
+impl Strategy for ColumnIsOutcome {
+    fn response(&self, game: &Game, opponent: ShapeId, column: &str) -> Result<ShapeId, AOCError> {
+        game.shape_for(opponent, column.parse::<Outcome>()?)
+    }
+}
+
+pub fn total_score(input: &str, game: &Game, strategy: &impl Strategy) -> BoxResult<usize> {
+    let mut res = 0;
+    for line in input.lines().filter(|line| !line.trim().is_empty()) {
+        let (opponent, column) = line.trim().split_once(' ').ok_or(AOCError)?;
+        let opponent = game.opponent_shape(opponent)?;
+        let me = strategy.response(game, opponent, column)?;
+        res += game.score(me, opponent);
+    }
     Ok(res)
 }
 
+// Game with any number of shapes, described by the list of shapes and a table
+// of `(winner, loser)` rules with exactly one winner for each pair of shapes.
+// A shape scores its 1-based rank in the list. In a strategy guide, both
+// columns name the shapes unless other codes are given with `with_codes`.
+pub struct Game {
+    shapes: Vec<String>,
+    beats: Vec<Vec<bool>>,
+    opponent_codes: Vec<String>,
+    response_codes: Vec<String>,
+}
+
+impl Game {
+    pub fn new(shapes: &[&str], rules: &[(&str, &str)]) -> Result<Self, AOCError> {
+        let shapes = shapes
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        if (1..shapes.len()).any(|i| shapes[..i].contains(&shapes[i])) {
+            return Err(AOCError);
+        }
+        let mut game = Game {
+            beats: vec![vec![false; shapes.len()]; shapes.len()],
+            opponent_codes: shapes.clone(),
+            response_codes: shapes.clone(),
+            shapes,
+        };
+        for (winner, loser) in rules {
+            let (w, l) = (game.shape(winner)?.0, game.shape(loser)?.0);
+            if w == l || game.beats[l][w] {
+                return Err(AOCError);
+            }
+            game.beats[w][l] = true;
+        }
+        let n = game.shapes.len();
+        let complete = (0..n).all(|a| (a + 1..n).all(|b| game.beats[a][b] != game.beats[b][a]));
+        if !complete {
+            return Err(AOCError);
+        }
+        Ok(game)
+    }
+
+    // Codes used for each shape, in order, in the opponent and response
+    // columns of a strategy guide.
+    pub fn with_codes(self, opponent: &[&str], response: &[&str]) -> Result<Self, AOCError> {
+        if opponent.len() != self.shapes.len() || response.len() != self.shapes.len() {
+            return Err(AOCError);
+        }
+        Ok(Game {
+            opponent_codes: opponent.iter().map(|s| s.to_string()).collect(),
+            response_codes: response.iter().map(|s| s.to_string()).collect(),
+            ..self
+        })
+    }
+
+    // Shapes coded `A`, `B`, `C` for the opponent and `X`, `Y`, `Z` for the
+    // response.
+    pub fn rock_paper_scissors() -> Self {
+        let names = ["Rock", "Paper", "Scissors"];
+        let rules = Shape::ALL
+            .iter()
+            .map(|s| (names[s.index()], names[s.beats().index()]))
+            .collect::<Vec<(&str, &str)>>();
+        Game::new(&names, &rules)
+            .and_then(|game| game.with_codes(&["A", "B", "C"], &["X", "Y", "Z"]))
+            .unwrap()
+    }
+
+    pub fn rock_paper_scissors_lizard_spock() -> Self {
+        Game::new(
+            &["Rock", "Paper", "Scissors", "Lizard", "Spock"],
+            &[
+                ("Scissors", "Paper"),
+                ("Paper", "Rock"),
+                ("Rock", "Lizard"),
+                ("Lizard", "Spock"),
+                ("Spock", "Scissors"),
+                ("Scissors", "Lizard"),
+                ("Lizard", "Paper"),
+                ("Paper", "Spock"),
+                ("Spock", "Rock"),
+                ("Rock", "Scissors"),
+            ],
+        )
+        .unwrap()
+    }
+
+    pub fn shape(&self, name: &str) -> Result<ShapeId, AOCError> {
+        self.shapes
+            .iter()
+            .position(|s| s == name)
+            .map(ShapeId)
+            .ok_or(AOCError)
+    }
+
+    pub fn name(&self, shape: ShapeId) -> &str {
+        &self.shapes[shape.0]
+    }
+
+    fn decode(codes: &[String], code: &str) -> Result<ShapeId, AOCError> {
+        codes
+            .iter()
+            .position(|c| c == code)
+            .map(ShapeId)
+            .ok_or(AOCError)
+    }
+
+    pub fn opponent_shape(&self, code: &str) -> Result<ShapeId, AOCError> {
+        Game::decode(&self.opponent_codes, code)
+    }
+
+    pub fn response_shape(&self, code: &str) -> Result<ShapeId, AOCError> {
+        Game::decode(&self.response_codes, code)
+    }
+
+    // First shape in the list that gets `outcome` against `opponent`.
+    pub fn shape_for(&self, opponent: ShapeId, outcome: Outcome) -> Result<ShapeId, AOCError> {
+        (0..self.shapes.len())
+            .map(ShapeId)
+            .find(|&shape| self.play(shape, opponent) == outcome)
+            .ok_or(AOCError)
+    }
+
+    pub fn play(&self, me: ShapeId, opponent: ShapeId) -> Outcome {
+        if self.beats[me.0][opponent.0] {
+            Outcome::Win
+        } else if self.beats[opponent.0][me.0] {
+            Outcome::Lose
+        } else {
+            Outcome::Draw
+        }
+    }
+
+    pub fn score(&self, me: ShapeId, opponent: ShapeId) -> usize {
+        me.0 + 1 + self.play(me, opponent).score()
+    }
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    total_score(input, &Game::rock_paper_scissors(), &ColumnIsShape)
+}
+
+fn part_two(input: &str) -> BoxResult<usize> {
+    total_score(input, &Game::rock_paper_scissors(), &ColumnIsOutcome)
+}
+
 pub fn run() {
     let input = &read_file_to_string("./inputs/day02.txt");
     solve!(1, part_one, input);
@@ -65,4 +256,43 @@ mod tests {
         let input = &read_file_to_string("./examples/day02.txt");
         assert_eq!(part_two(input).ok(), Some(12));
     }
+
+    #[test]
+    fn test_invalid_line() {
+        assert!(part_one("A Y\nA W\n").is_err());
+        assert!(part_two("D Y\n").is_err());
+        let rps = Game::rock_paper_scissors();
+        assert!(total_score("X Y\n", &rps, &ColumnIsShape).is_err());
+        assert!(total_score("A B\n", &rps, &ColumnIsShape).is_err());
+    }
+
+    #[test]
+    fn test_games() {
+        let rps = Game::rock_paper_scissors();
+        let [rock, paper, scissors] = ["Rock", "Paper", "Scissors"].map(|s| rps.shape(s).unwrap());
+        assert_eq!(rps.play(paper, rock), Outcome::Win);
+        assert_eq!(rps.score(scissors, rock), 3);
+        assert_eq!(rps.response_shape("Y").ok(), Some(paper));
+        assert_eq!(rps.name(rps.opponent_shape("C").unwrap()), "Scissors");
+
+        let game = Game::rock_paper_scissors_lizard_spock();
+        let [rock, paper, scissors, lizard, spock] =
+            ["Rock", "Paper", "Scissors", "Lizard", "Spock"].map(|s| game.shape(s).unwrap());
+        assert_eq!(game.play(spock, rock), Outcome::Win);
+        assert_eq!(game.play(lizard, scissors), Outcome::Lose);
+        assert_eq!(game.play(lizard, lizard), Outcome::Draw);
+        assert_eq!(game.score(spock, paper), 5);
+        assert!(game.shape("Well").is_err());
+        assert!(Game::new(&["A", "B"], &[("A", "B"), ("B", "A")]).is_err());
+        assert!(Game::new(&["A", "B", "C"], &[("A", "B")]).is_err());
+        assert!(Game::new(&["A", "A"], &[("A", "A")]).is_err());
+
+        let guide = "Rock Spock\nLizard Lizard\n";
+        assert_eq!(total_score(guide, &game, &ColumnIsShape).ok(), Some(11 + 7));
+        let guide = "Rock Z\nSpock X\n";
+        assert_eq!(
+            total_score(guide, &game, &ColumnIsOutcome).ok(),
+            Some(8 + 1)
+        );
+    }
 }
@@ -1,5 +1,4 @@
 use crate::{read_file_to_string, solve, AOCError, BoxResult};
-use itertools::{self, Itertools};
 
 fn get_char_value(byte: u8) -> Result<usize, AOCError> {
     match byte {
@@ -9,35 +8,96 @@ fn get_char_value(byte: u8) -> Result<usize, AOCError> {
     }
 }
 
-fn part_one(input: &str) -> BoxResult<usize> {
+fn get_value_char(priority: usize) -> char {
+    match priority {
+        1..=26 => (b'a' + (priority - 1) as u8) as char,
+        _ => (b'A' + (priority - 27) as u8) as char,
+    }
+}
+
+// Set of items where bit `p` is set when the item of priority `p` is present.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ItemSet(u64);
+
+impl ItemSet {
+    pub fn parse(items: &str) -> Result<Self, AOCError> {
+        items.bytes().try_fold(ItemSet(0), |set, b| {
+            Ok(ItemSet(set.0 | 1 << get_char_value(b)?))
+        })
+    }
+
+    pub fn intersection(sets: impl IntoIterator<Item = ItemSet>) -> ItemSet {
+        sets.into_iter()
+            .reduce(|a, b| ItemSet(a.0 & b.0))
+            .unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn priorities(&self) -> Vec<usize> {
+        (1..=52).filter(|p| self.0 & (1 << p) != 0).collect()
+    }
+
+    pub fn items(&self) -> Vec<char> {
+        self.priorities().into_iter().map(get_value_char).collect()
+    }
+
+    // Priority of the only item in the set.
+    pub fn single(&self) -> Result<usize, AOCError> {
+        if self.len() == 1 {
+            Ok(self.0.trailing_zeros() as usize)
+        } else {
+            Err(AOCError)
+        }
+    }
+}
+
+// Items found in both compartments of each rucksack.
+pub fn misplaced_items(input: &str) -> BoxResult<Vec<ItemSet>> {
     input
         .lines()
         .map(|line| {
             let (first, last) = line.split_at(line.len() / 2);
-            let duplicate = first
-                .bytes()
-                .find(|x| last.as_bytes().contains(x))
-                .ok_or(AOCError)?;
-            Ok(get_char_value(duplicate)?)
+            Ok(ItemSet::intersection([
+                ItemSet::parse(first)?,
+                ItemSet::parse(last)?,
+            ]))
         })
-        .sum()
+        .collect()
 }
 
-fn part_two(input: &str) -> BoxResult<usize> {
-    input
+// Items shared by all the rucksacks of each group of `group_size` elves.
+pub fn badges(input: &str, group_size: usize) -> BoxResult<Vec<ItemSet>> {
+    let rucksacks = input
         .lines()
-        .tuples()
-        .map(|(line1, line2, line3)| {
-            let duplicate = line1
-                .bytes()
-                .filter(|x| line2.as_bytes().contains(x))
-                .find(|x| line3.as_bytes().contains(x))
-                .ok_or(AOCError)?;
-            Ok(get_char_value(duplicate)?)
-        })
+        .map(ItemSet::parse)
+        .collect::<Result<Vec<ItemSet>, _>>()?;
+    if group_size == 0 || rucksacks.len() % group_size != 0 {
+        return Err(Box::new(AOCError));
+    }
+    Ok(rucksacks
+        .chunks(group_size)
+        .map(|group| ItemSet::intersection(group.iter().copied()))
+        .collect())
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    misplaced_items(input)?
+        .iter()
+        .map(|set| Ok(set.single()?))
         .sum()
 }
 
+fn part_two(input: &str) -> BoxResult<usize> {
+    badges(input, 3)?.iter().map(|set| Ok(set.single()?)).sum()
+}
+
 pub fn run() {
     let input = &read_file_to_string("./inputs/day03.txt");
     solve!(1, part_one, input);
@@ -60,4 +120,24 @@ mod tests {
         let input = &read_file_to_string("./examples/day03.txt");
         assert_eq!(part_two(input).ok(), Some(70));
     }
+
+    #[test]
+    fn test_item_sets() {
+        let input = &read_file_to_string("./examples/day03.txt");
+        let shared = misplaced_items(input).unwrap();
+        assert_eq!(shared[0].items(), vec!['p']);
+        assert_eq!(shared[1].priorities(), vec![38]);
+
+        let set = ItemSet::intersection([
+            ItemSet::parse("abcXY").unwrap(),
+            ItemSet::parse("bcdY").unwrap(),
+        ]);
+        assert_eq!(set.items(), vec!['b', 'c', 'Y']);
+        assert!(set.single().is_err());
+        assert!(ItemSet::parse("ab1").is_err());
+
+        let groups = badges(input, 6).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(badges(input, 4).is_err());
+    }
 }
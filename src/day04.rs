@@ -1,47 +1,145 @@
-use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::str::FromStr;
 
-use crate::{read_file_to_string, solve, BoxResult};
+use crate::{read_file_to_string, solve, AOCError, BoxResult};
 
-fn part_one(input: &str) -> BoxResult<usize> {
-    let re = Regex::new(r"(\d+)-(\d+),(\d+)-(\d+)").unwrap();
-    let res = input.lines().fold(0usize, |sum, line| {
-        let caps = re.captures(&line).unwrap();
-        let (first, second, third, last) = (
-            match_to_usize(caps.get(1).unwrap()),
-            match_to_usize(caps.get(2).unwrap()),
-            match_to_usize(caps.get(3).unwrap()),
-            match_to_usize(caps.get(4).unwrap()),
-        );
-        if (first >= third && second <= last) || (first <= third && second >= last) {
-            sum + 1
-        } else {
-            sum
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sections {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl FromStr for Sections {
+    type Err = AOCError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').ok_or(AOCError)?;
+        let sections = Sections {
+            start: start.trim().parse::<usize>().map_err(|_| AOCError)?,
+            end: end.trim().parse::<usize>().map_err(|_| AOCError)?,
+        };
+        if sections.start > sections.end {
+            return Err(AOCError);
         }
-    });
-    Ok(res)
+        Ok(sections)
+    }
 }
 
-fn match_to_usize(m: regex::Match) -> usize {
-    m.as_str().parse::<usize>().unwrap()
+// Allen's interval relations, read as "first <relation> second".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Relation {
+    Before,
+    Meets,
+    Overlaps,
+    FinishedBy,
+    Contains,
+    Starts,
+    Equals,
+    StartedBy,
+    During,
+    Finishes,
+    OverlappedBy,
+    MetBy,
+    After,
 }
 
-fn part_two(input: &str) -> BoxResult<usize> {
-    let re = Regex::new(r"(\d+)-(\d+),(\d+)-(\d+)").unwrap();
-    let res = input.lines().fold(0usize, |sum, line| {
-        let caps = re.captures(&line).unwrap();
-        let (first, second, third, last) = (
-            match_to_usize(caps.get(1).unwrap()),
-            match_to_usize(caps.get(2).unwrap()),
-            match_to_usize(caps.get(3).unwrap()),
-            match_to_usize(caps.get(4).unwrap()),
-        );
-        if first <= last && third <= second {
-            sum + 1
+impl Relation {
+    // One of the assignments holds every section of the other.
+    pub fn is_containment(self) -> bool {
+        use Relation::*;
+        matches!(
+            self,
+            FinishedBy | Contains | Starts | Equals | StartedBy | During | Finishes
+        )
+    }
+
+    // The assignments share at least one section.
+    pub fn is_overlapping(self) -> bool {
+        use Relation::*;
+        !matches!(self, Before | Meets | MetBy | After)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Assignment {
+    pub first: Sections,
+    pub second: Sections,
+}
+
+impl FromStr for Assignment {
+    type Err = AOCError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (first, second) = s.split_once(',').ok_or(AOCError)?;
+        Ok(Assignment {
+            first: first.parse()?,
+            second: second.parse()?,
+        })
+    }
+}
+
+impl Assignment {
+    // Sections are inclusive, so `2-4` meets `5-6` while `2-4` overlaps `4-6`.
+    pub fn relation(&self) -> Relation {
+        use Relation::*;
+        let (s1, e1) = (self.first.start, self.first.end + 1);
+        let (s2, e2) = (self.second.start, self.second.end + 1);
+
+        if e1 < s2 {
+            Before
+        } else if e1 == s2 {
+            Meets
+        } else if e2 < s1 {
+            After
+        } else if e2 == s1 {
+            MetBy
         } else {
-            sum
+            match (s1.cmp(&s2), e1.cmp(&e2)) {
+                (Ordering::Less, Ordering::Less) => Overlaps,
+                (Ordering::Less, Ordering::Equal) => FinishedBy,
+                (Ordering::Less, Ordering::Greater) => Contains,
+                (Ordering::Equal, Ordering::Less) => Starts,
+                (Ordering::Equal, Ordering::Equal) => Equals,
+                (Ordering::Equal, Ordering::Greater) => StartedBy,
+                (Ordering::Greater, Ordering::Less) => During,
+                (Ordering::Greater, Ordering::Equal) => Finishes,
+                (Ordering::Greater, Ordering::Greater) => OverlappedBy,
+            }
         }
-    });
-    Ok(res)
+    }
+}
+
+pub fn parse_assignments(input: &str) -> Result<Vec<Assignment>, AOCError> {
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.parse::<Assignment>())
+        .collect()
+}
+
+pub fn relation_counts(input: &str) -> BoxResult<BTreeMap<Relation, usize>> {
+    let mut counts = BTreeMap::new();
+    for assignment in parse_assignments(input)? {
+        *counts.entry(assignment.relation()).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    Ok(relation_counts(input)?
+        .iter()
+        .filter(|(relation, _)| relation.is_containment())
+        .map(|(_, count)| count)
+        .sum())
+}
+
+fn part_two(input: &str) -> BoxResult<usize> {
+    Ok(relation_counts(input)?
+        .iter()
+        .filter(|(relation, _)| relation.is_overlapping())
+        .map(|(_, count)| count)
+        .sum())
 }
 
 pub fn run() {
@@ -66,4 +164,24 @@ mod tests {
         let input = &read_file_to_string("./examples/day04.txt");
         assert_eq!(part_two(input).ok(), Some(4));
     }
+
+    #[test]
+    fn test_relations() {
+        let relation = |s: &str| s.parse::<Assignment>().unwrap().relation();
+        assert_eq!(relation("2-4,6-8"), Relation::Before);
+        assert_eq!(relation("2-4,5-8"), Relation::Meets);
+        assert_eq!(relation("5-7,7-9"), Relation::Overlaps);
+        assert_eq!(relation("2-8,3-7"), Relation::Contains);
+        assert_eq!(relation("6-6,4-6"), Relation::Finishes);
+        assert_eq!(relation("4-6,4-6"), Relation::Equals);
+        assert_eq!(relation("4-6,1-3"), Relation::MetBy);
+        assert!("4-2,1-3".parse::<Assignment>().is_err());
+        assert!("4-6;1-3".parse::<Assignment>().is_err());
+
+        let input = &read_file_to_string("./examples/day04.txt");
+        let counts = relation_counts(input).unwrap();
+        assert_eq!(counts.get(&Relation::Meets), Some(&1));
+        assert_eq!(counts.get(&Relation::Overlaps), Some(&2));
+        assert_eq!(counts.values().sum::<usize>(), 6);
+    }
 }
@@ -1,81 +1,244 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
 use regex::Regex;
 
-use crate::{read_file_to_string, solve, BoxResult};
+use crate::{read_file_to_string, solve, AOCError, BoxResult};
 
-fn part_one(input: &str) -> BoxResult<String> {
-    let (stack_str, procedures) = input.split_once("\n\n").unwrap();
-    let stack_lines: Vec<_> = stack_str.lines().collect();
-    let stack_count = stack_lines.last().unwrap().split_ascii_whitespace().count();
-    let mut stacks: Vec<Vec<char>> = vec![vec![]; stack_count];
-    stack_lines
-        .iter()
-        .rev()
-        .flat_map(|row| {
-            row.chars()
-                .skip(1)
-                .step_by(4)
-                .enumerate()
-                .filter(|(_, c)| !c.is_ascii_whitespace())
-        })
-        .for_each(|(i, c)| stacks[i].push(c));
-    let re = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
-    procedures.lines().for_each(|line| {
-        if let Some(caps) = re.captures(&line) {
-            let (first, second, third) = (
-                match_to_usize(caps.get(1).unwrap()),
-                match_to_usize(caps.get(2).unwrap()),
-                match_to_usize(caps.get(3).unwrap()),
-            );
-            (0..first).for_each(|_i| {
-                if let Some(val) = stacks[second - 1].pop() {
-                    stacks[third - 1].push(val);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stacks {
+    stacks: Vec<Vec<String>>,
+}
+
+// Columns are `width` characters wide and separated by a single space, the
+// width being found from the longest line of the drawing.
+impl FromStr for Stacks {
+    type Err = AOCError;
+
+    fn from_str(drawing: &str) -> Result<Self, Self::Err> {
+        let stack_lines: Vec<_> = drawing.lines().collect();
+        let stack_count = stack_lines
+            .last()
+            .ok_or(AOCError)?
+            .split_ascii_whitespace()
+            .count();
+        if stack_count == 0 {
+            return Err(AOCError);
+        }
+        let line_len = stack_lines.iter().map(|l| l.len()).max().unwrap();
+        let width = (line_len + 1).div_ceil(stack_count) - 1;
+
+        let mut stacks: Vec<Vec<String>> = vec![vec![]; stack_count];
+        for row in stack_lines.iter().rev().skip(1) {
+            for (i, stack) in stacks.iter_mut().enumerate() {
+                let start = (i * (width + 1)).min(row.len());
+                let cell = row[start..(start + width).min(row.len())].trim();
+                if cell.is_empty() {
+                    continue;
                 }
+                let label = cell
+                    .strip_prefix('[')
+                    .and_then(|c| c.strip_suffix(']'))
+                    .ok_or(AOCError)?;
+                stack.push(label.to_string());
+            }
+        }
+        Ok(Stacks { stacks })
+    }
+}
+
+impl fmt::Display for Stacks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .stacks
+            .iter()
+            .flatten()
+            .map(|label| label.len())
+            .chain([self.stacks.len().to_string().len()])
+            .max()
+            .unwrap_or(1)
+            + 2;
+        let height = self.stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+        for level in (0..height).rev() {
+            let row = self
+                .stacks
+                .iter()
+                .map(|s| match s.get(level) {
+                    Some(label) => format!("{:<width$}", format!("[{}]", label)),
+                    None => " ".repeat(width),
+                })
+                .collect::<Vec<String>>();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        let labels = (1..=self.stacks.len())
+            .map(|i| format!("{:^width$}", i))
+            .collect::<Vec<String>>();
+        write!(f, "{}", labels.join(" "))
+    }
+}
+
+impl Stacks {
+    // Stacks listed left to right, each one from the bottom crate up.
+    pub fn new(stacks: Vec<Vec<String>>) -> Self {
+        Stacks { stacks }
+    }
+
+    pub fn stacks(&self) -> &[Vec<String>] {
+        &self.stacks
+    }
+
+    // Crate on top of each stack, empty stacks are skipped.
+    pub fn top(&self) -> String {
+        self.stacks
+            .iter()
+            .filter_map(|s| s.last().map(|label| label.as_str()))
+            .collect()
+    }
+
+    pub fn apply(&mut self, step: &Move, crane: &impl Crane) -> Result<(), MoveError> {
+        for stack in [step.from, step.to] {
+            if stack == 0 || stack > self.stacks.len() {
+                return Err(MoveError::NoSuchStack(stack));
+            }
+        }
+        let from = &mut self.stacks[step.from - 1];
+        if from.len() < step.count {
+            return Err(MoveError::NotEnoughCrates {
+                stack: step.from,
+                needed: step.count,
+                available: from.len(),
             });
         }
-    });
-    let res = stacks.iter().fold(String::from(""), |acc, arr| {
-        acc + &arr.last().unwrap().to_string()
-    });
-    Ok(res)
+        let lifted = from.split_off(from.len() - step.count);
+        self.stacks[step.to - 1].extend(crane.unload(lifted));
+        Ok(())
+    }
 }
 
-fn match_to_usize(m: regex::Match) -> usize {
-    m.as_str().parse::<usize>().unwrap()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub count: usize,
+    pub from: usize,
+    pub to: usize,
 }
 
-fn part_two(input: &str) -> BoxResult<String> {
-    let (stack_str, procedures) = input.split_once("\n\n").unwrap();
-    let stack_lines: Vec<_> = stack_str.lines().collect();
-    let stack_count = stack_lines.last().unwrap().split_ascii_whitespace().count();
-    let mut stacks: Vec<Vec<char>> = vec![vec![]; stack_count];
-    stack_lines
-        .iter()
-        .rev()
-        .flat_map(|row| {
-            row.chars()
-                .skip(1)
-                .step_by(4)
-                .enumerate()
-                .filter(|(_, c)| !c.is_ascii_whitespace())
-        })
-        .for_each(|(i, c)| stacks[i].push(c));
-    let re = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
-    procedures.lines().for_each(|line| {
-        if let Some(caps) = re.captures(&line) {
-            let (first, second, third) = (
-                match_to_usize(caps.get(1).unwrap()),
-                match_to_usize(caps.get(2).unwrap()),
-                match_to_usize(caps.get(3).unwrap()),
-            );
-            let range_drain = stacks[second - 1].len() - first;
-            let dra: Vec<char> = stacks[second - 1].drain(range_drain..).collect();
-            dra.iter().for_each(|v| stacks[third - 1].push(*v));
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "move {} from {} to {}", self.count, self.from, self.to)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    NoSuchStack(usize),
+    NotEnoughCrates {
+        stack: usize,
+        needed: usize,
+        available: usize,
+    },
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::NoSuchStack(stack) => write!(f, "no stack {}", stack),
+            MoveError::NotEnoughCrates {
+                stack,
+                needed,
+                available,
+            } => write!(
+                f,
+                "cannot move {} crates from stack {} holding {}",
+                needed, stack, available
+            ),
         }
-    });
-    let res = stacks.iter().fold(String::from(""), |acc, arr| {
-        acc + &arr.last().unwrap().to_string()
-    });
-    Ok(res)
+    }
+}
+
+impl Error for MoveError {}
+
+// Order in which lifted crates, listed bottom first, are put down.
+pub trait Crane {
+    fn unload(&self, crates: Vec<String>) -> Vec<String>;
+}
+
+// Moves crates one at a time, so the lifted ones end up reversed.
+pub struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn unload(&self, mut crates: Vec<String>) -> Vec<String> {
+        crates.reverse();
+        crates
+    }
+}
+
+// Moves all the lifted crates at once and keeps their order.
+pub struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn unload(&self, crates: Vec<String>) -> Vec<String> {
+        crates
+    }
+}
+
+pub fn parse_procedure(input: &str) -> BoxResult<(Stacks, Vec<Move>)> {
+    let (drawing, procedures) = input.split_once("\n\n").ok_or(AOCError)?;
+    let re = Regex::new(r"^move (\d+) from (\d+) to (\d+)$").unwrap();
+    let moves = procedures
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let caps = re.captures(line.trim()).ok_or(AOCError)?;
+            Ok(Move {
+                count: match_to_usize(caps.get(1).unwrap())?,
+                from: match_to_usize(caps.get(2).unwrap())?,
+                to: match_to_usize(caps.get(3).unwrap())?,
+            })
+        })
+        .collect::<Result<Vec<Move>, AOCError>>()?;
+    Ok((drawing.parse()?, moves))
+}
+
+fn match_to_usize(m: regex::Match) -> Result<usize, AOCError> {
+    m.as_str().parse::<usize>().map_err(|_| AOCError)
+}
+
+// Writes the drawing and the move list in the puzzle input format.
+pub fn write_procedure(stacks: &Stacks, moves: &[Move]) -> String {
+    let moves = moves
+        .iter()
+        .map(|step| step.to_string())
+        .collect::<Vec<String>>();
+    format!("{}\n\n{}\n", stacks, moves.join("\n"))
+}
+
+// State of the stacks after each move, the initial drawing first.
+pub fn simulate(input: &str, crane: &impl Crane) -> BoxResult<Vec<Stacks>> {
+    let (mut stacks, moves) = parse_procedure(input)?;
+    let mut log = vec![stacks.clone()];
+    for step in &moves {
+        stacks.apply(step, crane)?;
+        log.push(stacks.clone());
+    }
+    Ok(log)
+}
+
+// Final state of the stacks, the moves being applied in place.
+pub fn rearrange(input: &str, crane: &impl Crane) -> BoxResult<Stacks> {
+    let (mut stacks, moves) = parse_procedure(input)?;
+    for step in &moves {
+        stacks.apply(step, crane)?;
+    }
+    Ok(stacks)
+}
+
+fn part_one(input: &str) -> BoxResult<String> {
+    Ok(rearrange(input, &CrateMover9000)?.top())
+}
+
+fn part_two(input: &str) -> BoxResult<String> {
+    Ok(rearrange(input, &CrateMover9001)?.top())
 }
 
 pub fn run() {
@@ -88,6 +251,7 @@ pub fn run() {
 mod tests {
 
     use super::*;
+    use crate::test_utils::XorShift;
 
     #[test]
     fn test_part_one() {
@@ -100,4 +264,83 @@ mod tests {
         let input = &read_file_to_string("./examples/day05.txt");
         assert_eq!(part_two(input).ok(), Some(String::from("MCD")));
     }
+
+    #[test]
+    fn test_simulate() {
+        let input = &read_file_to_string("./examples/day05.txt");
+        let log = simulate(input, &CrateMover9000).unwrap();
+        assert_eq!(log.len(), 5);
+        assert_eq!(
+            log.last(),
+            Some(&rearrange(input, &CrateMover9000).unwrap())
+        );
+        assert_eq!(log[0].to_string(), input.split_once("\n\n").unwrap().0);
+        assert_eq!(
+            log[2].to_string(),
+            [
+                "        [Z]",
+                "        [N]",
+                "    [C] [D]",
+                "    [M] [P]",
+                " 1   2   3 "
+            ]
+            .join("\n")
+        );
+
+        let (mut stacks, _) = parse_procedure(input).unwrap();
+        let step = Move {
+            count: 4,
+            from: 1,
+            to: 2,
+        };
+        assert_eq!(
+            stacks.apply(&step, &CrateMover9001),
+            Err(MoveError::NotEnoughCrates {
+                stack: 1,
+                needed: 4,
+                available: 2
+            })
+        );
+    }
+
+    fn random_procedure(rng: &mut XorShift) -> (Stacks, Vec<Move>) {
+        let stacks = (0..1 + rng.next(14))
+            .map(|_| {
+                (0..rng.next(6))
+                    .map(|_| {
+                        (0..1 + rng.next(3))
+                            .map(|_| (b'A' + rng.next(26) as u8) as char)
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect::<Vec<Vec<String>>>();
+        let moves = (0..rng.next(5))
+            .map(|_| Move {
+                count: 1 + rng.next(3),
+                from: 1 + rng.next(stacks.len()),
+                to: 1 + rng.next(stacks.len()),
+            })
+            .collect();
+        (Stacks::new(stacks), moves)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = &read_file_to_string("./examples/day05.txt");
+        let (stacks, moves) = parse_procedure(input).unwrap();
+        assert_eq!(
+            write_procedure(&stacks, &moves).trim_end(),
+            input.trim_end()
+        );
+
+        let mut rng = XorShift(0x2022_1205);
+        for _ in 0..200 {
+            let (stacks, moves) = random_procedure(&mut rng);
+            let written = write_procedure(&stacks, &moves);
+            let (parsed_stacks, parsed_moves) = parse_procedure(&written).unwrap();
+            assert_eq!(parsed_stacks, stacks, "{}", written);
+            assert_eq!(parsed_moves, moves);
+        }
+    }
 }
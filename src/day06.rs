@@ -1,29 +1,78 @@
-use std::collections::HashSet;
+use std::io::{BufReader, Read};
 
 use crate::{read_file_to_string, solve, AOCError, BoxResult};
 
-fn part_one(input: &str) -> BoxResult<usize> {
-    let chars: Vec<char> = input.chars().collect();
-    for i in 0..chars.len() {
-        let sl: HashSet<&char> = HashSet::from_iter(&chars[i..i + 4]);
-        if sl.len() == 4 {
-            return Ok(i + 4);
+// Tracks the longest run of distinct bytes ending at the current position by
+// remembering where each byte was last seen, so each byte costs O(1).
+struct MarkerScanner {
+    window: usize,
+    last_seen: [usize; 256],
+    run_start: usize,
+    position: usize,
+}
+
+impl MarkerScanner {
+    fn new(window: usize) -> Self {
+        MarkerScanner {
+            window,
+            last_seen: [0; 256],
+            run_start: 0,
+            position: 0,
         }
     }
-    Err(Box::new(AOCError))
+
+    // Returns the number of bytes read so far if the last `window` ones are
+    // all different.
+    fn push(&mut self, byte: u8) -> Option<usize> {
+        let seen = &mut self.last_seen[byte as usize];
+        self.run_start = self.run_start.max(*seen);
+        self.position += 1;
+        *seen = self.position;
+        (self.window > 0 && self.position - self.run_start >= self.window).then_some(self.position)
+    }
 }
 
-fn part_two(input: &str) -> BoxResult<usize> {
-    let chars: Vec<char> = input.chars().collect();
-    for i in 0..chars.len() {
-        let sl: HashSet<&char> = HashSet::from_iter(&chars[i..i + 14]);
-        if sl.len() == 14 {
-            return Ok(i + 14);
+pub fn find_marker(stream: &[u8], window: usize) -> Result<usize, AOCError> {
+    let mut scanner = MarkerScanner::new(window);
+    stream
+        .iter()
+        .find_map(|&byte| scanner.push(byte))
+        .ok_or(AOCError)
+}
+
+// Every position where the previous `window` bytes are all different.
+pub fn find_all_markers(stream: &[u8], window: usize) -> Vec<usize> {
+    let mut scanner = MarkerScanner::new(window);
+    stream
+        .iter()
+        .filter_map(|&byte| scanner.push(byte))
+        .collect()
+}
+
+// Reads the stream up to the end of its first line, the line break is not
+// part of the signal.
+pub fn find_marker_in<R: Read>(reader: R, window: usize) -> BoxResult<usize> {
+    let mut scanner = MarkerScanner::new(window);
+    for byte in BufReader::new(reader).bytes() {
+        let byte = byte?;
+        if byte == b'\n' || byte == b'\r' {
+            break;
+        }
+        if let Some(position) = scanner.push(byte) {
+            return Ok(position);
         }
     }
     Err(Box::new(AOCError))
 }
 
+fn part_one(input: &str) -> BoxResult<usize> {
+    Ok(find_marker(input.trim_end().as_bytes(), 4)?)
+}
+
+fn part_two(input: &str) -> BoxResult<usize> {
+    Ok(find_marker(input.trim_end().as_bytes(), 14)?)
+}
+
 pub fn run() {
     let input = &read_file_to_string("./inputs/day06.txt");
     solve!(1, part_one, input);
@@ -46,4 +95,25 @@ mod tests {
         let input = &read_file_to_string("./examples/day06.txt");
         assert_eq!(part_two(input).ok(), Some(23));
     }
+
+    #[test]
+    fn test_markers() {
+        assert_eq!(
+            find_marker(b"mjqjpqmgbljsphdztnvjfqwrcgsmlb", 4).ok(),
+            Some(7)
+        );
+        assert_eq!(
+            find_marker(b"mjqjpqmgbljsphdztnvjfqwrcgsmlb", 14).ok(),
+            Some(19)
+        );
+        assert!(find_marker(b"aabbaabb", 3).is_err());
+        assert_eq!(find_all_markers(b"abcabbcd", 3), vec![3, 4, 5, 8]);
+        assert_eq!(
+            find_marker_in("nppdvjthqldpwncqszvftbrmjlhg".as_bytes(), 4).ok(),
+            Some(6)
+        );
+        assert!(find_marker_in("aaaa".as_bytes(), 2).is_err());
+        assert!(find_marker_in("abc\n".as_bytes(), 4).is_err());
+        assert!(find_marker_in("abc\r\nd".as_bytes(), 4).is_err());
+    }
 }
@@ -1,95 +1,303 @@
-use crate::{read_file_to_string, solve, BoxResult};
-use itertools::FoldWhile::{Continue, Done};
-use itertools::Itertools;
-use std::cmp;
-fn part_one(input: &str) -> BoxResult<usize> {
-    let trees: Vec<Vec<usize>> = input
-        .lines()
-        .map(|line| {
-            line.chars()
-                .map(|char| char.to_digit(10).unwrap() as usize)
-                .collect::<Vec<usize>>()
-        })
-        .collect();
-    let mut count_visible_trees: usize = 0;
-    trees.iter().enumerate().for_each(|(y, row)| {
-        row.iter().enumerate().for_each(|(x, value)| {
-            let column = trees.iter().map(|r| r[x]).collect::<Vec<usize>>();
-            if x == 0 || y == 0 || x == row.len() - 1 || y == column.len() - 1 {
-                count_visible_trees += 1;
-            } else if value > row[..x].iter().max().unwrap()
-                || value > row[x + 1..].iter().max().unwrap()
-            {
-                count_visible_trees += 1;
-            } else if value > column[..y].iter().max().unwrap()
-                || value > column[y + 1..].iter().max().unwrap()
-            {
-                count_visible_trees += 1;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{pgm, read_file_to_string, solve, AOCError, BoxResult};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Tree heights along with, for every tree and direction, whether the tree can
+// be seen from the edge on that side and how many trees it can see that way.
+#[derive(Clone, Debug)]
+pub struct Forest {
+    heights: Vec<Vec<u8>>,
+    visible: [Vec<Vec<bool>>; 4],
+    distances: [Vec<Vec<usize>>; 4],
+}
+
+impl FromStr for Forest {
+    type Err = AOCError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let heights = input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.trim()
+                    .bytes()
+                    .map(|b| match b {
+                        b'0'..=b'9' => Ok(b - b'0'),
+                        _ => Err(AOCError),
+                    })
+                    .collect::<Result<Vec<u8>, AOCError>>()
+            })
+            .collect::<Result<Vec<Vec<u8>>, AOCError>>()?;
+        if heights
+            .windows(2)
+            .any(|rows| rows[0].len() != rows[1].len())
+        {
+            return Err(AOCError);
+        }
+        Ok(Forest::new(heights))
+    }
+}
+
+impl Forest {
+    pub fn new(heights: Vec<Vec<u8>>) -> Self {
+        let (width, height) = (heights.first().map_or(0, |row| row.len()), heights.len());
+        let mut forest = Forest {
+            heights,
+            visible: Default::default(),
+            distances: Default::default(),
+        };
+        for direction in Direction::ALL {
+            // Each line is walked starting from the edge the trees look toward.
+            let lines: Vec<Vec<(usize, usize)>> = match direction {
+                Direction::Up => (0..width)
+                    .map(|x| (0..height).map(|y| (x, y)).collect())
+                    .collect(),
+                Direction::Down => (0..width)
+                    .map(|x| (0..height).rev().map(|y| (x, y)).collect())
+                    .collect(),
+                Direction::Left => (0..height)
+                    .map(|y| (0..width).map(|x| (x, y)).collect())
+                    .collect(),
+                Direction::Right => (0..height)
+                    .map(|y| (0..width).rev().map(|x| (x, y)).collect())
+                    .collect(),
+            };
+            let mut visible = vec![vec![false; width]; height];
+            let mut distances = vec![vec![0; width]; height];
+            for line in lines {
+                forest.sweep(&line, &mut visible, &mut distances);
+            }
+            forest.visible[direction.index()] = visible;
+            forest.distances[direction.index()] = distances;
+        }
+        forest
+    }
+
+    // Keeps a stack of the trees seen so far with strictly decreasing heights:
+    // once the shorter ones are popped, the top is the closest blocking tree.
+    fn sweep(
+        &self,
+        line: &[(usize, usize)],
+        visible: &mut [Vec<bool>],
+        distances: &mut [Vec<usize>],
+    ) {
+        let mut stack: Vec<(usize, u8)> = vec![];
+        for (step, &(x, y)) in line.iter().enumerate() {
+            let tree = self.heights[y][x];
+            while stack.last().is_some_and(|&(_, other)| other < tree) {
+                stack.pop();
             }
-        });
-    });
-    Ok(count_visible_trees)
+            match stack.last() {
+                Some(&(blocker, _)) => distances[y][x] = step - blocker,
+                None => {
+                    distances[y][x] = step;
+                    visible[y][x] = true;
+                }
+            }
+            stack.push((step, tree));
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.heights.first().map_or(0, |row| row.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.heights.len()
+    }
+
+    pub fn tree(&self, x: usize, y: usize) -> u8 {
+        self.heights[y][x]
+    }
+
+    pub fn is_visible_from(&self, x: usize, y: usize, direction: Direction) -> bool {
+        self.visible[direction.index()][y][x]
+    }
+
+    pub fn is_visible(&self, x: usize, y: usize) -> bool {
+        Direction::ALL
+            .iter()
+            .any(|&direction| self.is_visible_from(x, y, direction))
+    }
+
+    pub fn viewing_distance(&self, x: usize, y: usize, direction: Direction) -> usize {
+        self.distances[direction.index()][y][x]
+    }
+
+    pub fn scenic_score(&self, x: usize, y: usize) -> usize {
+        Direction::ALL
+            .iter()
+            .map(|&direction| self.viewing_distance(x, y, direction))
+            .product()
+    }
+
+    pub fn visibility(&self) -> Vec<Vec<bool>> {
+        (0..self.height())
+            .map(|y| (0..self.width()).map(|x| self.is_visible(x, y)).collect())
+            .collect()
+    }
+
+    pub fn scenic_scores(&self) -> Vec<Vec<usize>> {
+        (0..self.height())
+            .map(|y| (0..self.width()).map(|x| self.scenic_score(x, y)).collect())
+            .collect()
+    }
+
+    // Every tree sharing the highest scenic score, in reading order.
+    pub fn best_trees(&self) -> Vec<(usize, usize)> {
+        let scores = self.scenic_scores();
+        let best = scores.iter().flatten().max().copied().unwrap_or(0);
+        (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| scores[y][x] == best)
+            .collect()
+    }
+
+    // Number of edges each tree can be seen from, `.` when it is hidden.
+    pub fn visibility_ascii(&self) -> String {
+        self.visible_sides()
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&n| match n {
+                        0 => '.',
+                        n => char::from_digit(n as u32, 10).unwrap(),
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Scenic scores scaled against the best one, from ` ` up to `@`.
+    pub fn scenic_ascii(&self) -> String {
+        const RAMP: &[u8] = b" .:-=+*#%@";
+        let scores = self.scenic_scores();
+        let best = scores.iter().flatten().max().copied().unwrap_or(0).max(1);
+        scores
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&score| RAMP[score * (RAMP.len() - 1) / best] as char)
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn visibility_pgm(&self) -> String {
+        pgm(&self.visible_sides(), Direction::ALL.len())
+    }
+
+    pub fn scenic_pgm(&self) -> String {
+        let scores = self.scenic_scores();
+        let best = scores.iter().flatten().max().copied().unwrap_or(0);
+        pgm(&scores, best.max(1))
+    }
+
+    fn visible_sides(&self) -> Vec<Vec<usize>> {
+        (0..self.height())
+            .map(|y| {
+                (0..self.width())
+                    .map(|x| {
+                        Direction::ALL
+                            .iter()
+                            .filter(|&&direction| self.is_visible_from(x, y, direction))
+                            .count()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// Location, height and viewing distances of the best trees.
+pub fn report(input: &str) -> BoxResult<String> {
+    let forest = input.parse::<Forest>()?;
+    let best = forest.best_trees();
+    let &(x, y) = best.first().ok_or(AOCError)?;
+    let mut lines = vec![format!("best scenic score: {}", forest.scenic_score(x, y))];
+    for (x, y) in best {
+        let distances = Direction::ALL
+            .iter()
+            .map(|&direction| format!("{} {}", direction, forest.viewing_distance(x, y, direction)))
+            .collect::<Vec<String>>();
+        lines.push(format!(
+            "({}, {}) height {}: {}",
+            x,
+            y,
+            forest.tree(x, y),
+            distances.join(", ")
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+// Prints the report and heatmaps for the real input, and also writes them as
+// images when given a directory.
+pub fn run_report(pgm_dir: Option<&Path>) -> BoxResult<()> {
+    let input = &read_file_to_string("./inputs/day08.txt");
+    let forest = input.parse::<Forest>()?;
+    println!("{}\n", report(input)?);
+    println!("visibility:\n{}\n", forest.visibility_ascii());
+    println!("scenic scores:\n{}", forest.scenic_ascii());
+    if let Some(dir) = pgm_dir {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("visibility.pgm"), forest.visibility_pgm())?;
+        fs::write(dir.join("scenic.pgm"), forest.scenic_pgm())?;
+    }
+    Ok(())
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    let forest = input.parse::<Forest>()?;
+    Ok(forest.visibility().iter().flatten().filter(|&&v| v).count())
 }
 
 fn part_two(input: &str) -> BoxResult<usize> {
-    let trees: Vec<Vec<usize>> = input
-        .lines()
-        .map(|line| {
-            line.chars()
-                .map(|char| char.to_digit(10).unwrap() as usize)
-                .collect::<Vec<usize>>()
-        })
-        .collect();
-    let mut highest_scenic_score: usize = 0;
-    trees.iter().enumerate().for_each(|(y, row)| {
-        row.iter().enumerate().for_each(|(x, value)| {
-            let column = trees.iter().map(|r| r[x]).collect::<Vec<usize>>();
-            let left = row[..x]
-                .iter()
-                .rev()
-                .fold_while(0usize, |acc, xi| {
-                    if xi >= value {
-                        Done(acc + 1)
-                    } else {
-                        Continue(acc + 1)
-                    }
-                })
-                .into_inner();
-            let right = row[x + 1..]
-                .iter()
-                .fold_while(0usize, |acc, xi| {
-                    if xi >= value {
-                        Done(acc + 1)
-                    } else {
-                        Continue(acc + 1)
-                    }
-                })
-                .into_inner();
-            let up = column[..y]
-                .iter()
-                .rev()
-                .fold_while(0usize, |acc, yi| {
-                    if yi >= value {
-                        Done(acc + 1)
-                    } else {
-                        Continue(acc + 1)
-                    }
-                })
-                .into_inner();
-            let down = column[y + 1..]
-                .iter()
-                .fold_while(0usize, |acc, yi| {
-                    if yi >= value {
-                        Done(acc + 1)
-                    } else {
-                        Continue(acc + 1)
-                    }
-                })
-                .into_inner();
-            highest_scenic_score = cmp::max(highest_scenic_score, left * right * up * down)
-        });
-    });
-    Ok(highest_scenic_score)
+    let forest = input.parse::<Forest>()?;
+    Ok(forest
+        .scenic_scores()
+        .into_iter()
+        .flatten()
+        .max()
+        .ok_or(AOCError)?)
 }
 
 pub fn run() {
@@ -102,6 +310,7 @@ pub fn run() {
 mod tests {
 
     use super::*;
+    use crate::test_utils::XorShift;
 
     #[test]
     fn test_part_one() {
@@ -114,4 +323,97 @@ mod tests {
         let input = &read_file_to_string("./examples/day08.txt");
         assert_eq!(part_two(input).ok(), Some(8));
     }
+
+    #[test]
+    fn test_forest() {
+        let input = &read_file_to_string("./examples/day08.txt");
+        let forest = input.parse::<Forest>().unwrap();
+        assert_eq!((forest.width(), forest.height()), (5, 5));
+        assert_eq!(forest.viewing_distance(2, 1, Direction::Up), 1);
+        assert_eq!(forest.viewing_distance(2, 1, Direction::Down), 2);
+        assert_eq!(forest.scenic_score(2, 1), 4);
+        assert_eq!(forest.scenic_score(2, 3), 8);
+        assert!(forest.is_visible_from(1, 1, Direction::Left));
+        assert!(!forest.is_visible_from(1, 1, Direction::Right));
+        assert!(!forest.is_visible(3, 3));
+        assert!("123\n45\n".parse::<Forest>().is_err());
+        assert!("12a\n".parse::<Forest>().is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let input = &read_file_to_string("./examples/day08.txt");
+        let forest = input.parse::<Forest>().unwrap();
+        assert_eq!(forest.best_trees(), vec![(2, 3)]);
+        assert_eq!(
+            report(input).unwrap(),
+            [
+                "best scenic score: 8",
+                "(2, 3) height 5: up 2, down 1, left 2, right 2"
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            forest.visibility_ascii(),
+            ["21132", "122.1", "41.11", "1.2.4", "22142"].join("\n")
+        );
+        assert_eq!(forest.scenic_ascii().lines().nth(3), Some(" .@- "));
+        assert!(forest
+            .visibility_pgm()
+            .starts_with("P2\n5 5\n255\n127 63 63 191 127\n"));
+        assert!(forest
+            .scenic_pgm()
+            .ends_with("\n0 31 255 95 0\n0 0 0 0 0\n"));
+
+        let wide = Forest::new(vec![(0..100).map(|i| (i % 10) as u8).collect(); 3]);
+        let pgm = wide.scenic_pgm();
+        assert!(pgm.lines().all(|line| line.len() <= 70));
+        let pixels = pgm.lines().skip(3).flat_map(|l| l.split(' ')).count();
+        assert_eq!(pixels, 300);
+    }
+
+    // Walks away from the tree until the view is blocked, as the puzzle states.
+    fn look(heights: &[Vec<u8>], x: usize, y: usize, direction: Direction) -> (bool, usize) {
+        let (dx, dy) = match direction {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        };
+        let (mut cx, mut cy, mut distance) = (x as isize, y as isize, 0);
+        loop {
+            (cx, cy) = (cx + dx, cy + dy);
+            let Some(other) = heights
+                .get(cy as usize)
+                .and_then(|row| row.get(cx as usize))
+            else {
+                return (true, distance);
+            };
+            distance += 1;
+            if *other >= heights[y][x] {
+                return (false, distance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_against_brute_force() {
+        let mut rng = XorShift(0x2022_1208);
+        for _ in 0..50 {
+            let (width, height) = (1 + rng.next(12), 1 + rng.next(12));
+            let heights = (0..height)
+                .map(|_| (0..width).map(|_| rng.next(10) as u8).collect())
+                .collect::<Vec<Vec<u8>>>();
+            let forest = Forest::new(heights.clone());
+            for y in 0..height {
+                for x in 0..width {
+                    for direction in Direction::ALL {
+                        let (visible, distance) = look(&heights, x, y, direction);
+                        assert_eq!(forest.is_visible_from(x, y, direction), visible);
+                        assert_eq!(forest.viewing_distance(x, y, direction), distance);
+                    }
+                }
+            }
+        }
+    }
 }
@@ -1,15 +1,30 @@
 use itertools::Itertools;
 use std::collections::HashSet;
-use std::ops;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::{fmt, ops};
 
 use crate::{read_file_to_string, solve, AOCError, BoxResult};
 
-#[derive(Hash, Eq, PartialEq, Debug, Clone, Copy)]
+#[derive(Hash, Eq, PartialEq, Debug, Clone, Copy, Default)]
 struct Point {
     x: isize,
     y: isize,
 }
 
+impl ops::Add<Point> for Point {
+    type Output = Point;
+
+    fn add(self, rhs: Point) -> Self::Output {
+        Self {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+        }
+    }
+}
+
 impl ops::Sub<Point> for Point {
     type Output = Point;
 
@@ -21,174 +36,226 @@ impl ops::Sub<Point> for Point {
     }
 }
 
-struct RopeV1 {
-    head: Point,
-    tail: Point,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Up(usize),
+    Down(usize),
+    Left(usize),
+    Right(usize),
 }
 
-impl RopeV1 {
-    fn default() -> RopeV1 {
-        RopeV1 {
-            head: Point { x: 0, y: 0 },
-            tail: Point { x: 0, y: 0 },
+impl Motion {
+    fn step(self) -> (Point, usize) {
+        match self {
+            Motion::Up(n) => (Point { x: 0, y: 1 }, n),
+            Motion::Down(n) => (Point { x: 0, y: -1 }, n),
+            Motion::Left(n) => (Point { x: -1, y: 0 }, n),
+            Motion::Right(n) => (Point { x: 1, y: 0 }, n),
         }
     }
+}
 
-    fn move_tail(&mut self) {
-        let p_diff: Point = self.head - self.tail;
+impl FromStr for Motion {
+    type Err = AOCError;
 
-        if p_diff.x.abs() > 1 && p_diff.y == 0 {
-            self.tail.x += p_diff.x.signum();
-        } else if p_diff.y.abs() > 1 && p_diff.x == 0 {
-            self.tail.y += p_diff.y.signum();
-        } else if p_diff.x.abs() + p_diff.y.abs() > 2 {
-            self.tail.x += p_diff.x.signum();
-            self.tail.y += p_diff.y.signum();
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (direction, steps) = line
+            .split_ascii_whitespace()
+            .collect_tuple()
+            .ok_or(AOCError)?;
+        let steps = steps.parse::<usize>().map_err(|_| AOCError)?;
+        match direction {
+            "U" => Ok(Motion::Up(steps)),
+            "D" => Ok(Motion::Down(steps)),
+            "L" => Ok(Motion::Left(steps)),
+            "R" => Ok(Motion::Right(steps)),
+            _ => Err(AOCError),
         }
     }
+}
 
-    fn up(&mut self) {
-        self.head.y += 1;
-        self.move_tail();
-    }
-    fn left(&mut self) {
-        self.head.x -= 1;
-        self.move_tail();
-    }
-    fn right(&mut self) {
-        self.head.x += 1;
-        self.move_tail();
-    }
-    fn down(&mut self) {
-        self.head.y -= 1;
-        self.move_tail();
+impl fmt::Display for Motion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Motion::Up(n) => write!(f, "U {}", n),
+            Motion::Down(n) => write!(f, "D {}", n),
+            Motion::Left(n) => write!(f, "L {}", n),
+            Motion::Right(n) => write!(f, "R {}", n),
+        }
     }
 }
 
-struct RopeV2 {
-    head: Point,
-    tails: [Point; 9],
+pub fn parse_motions(input: &str) -> Result<Vec<Motion>, AOCError> {
+    input.lines().map(|line| line.parse::<Motion>()).collect()
 }
 
-impl RopeV2 {
-    fn default() -> RopeV2 {
-        RopeV2 {
-            head: Point { x: 0, y: 0 },
-            tails: [Point { x: 0, y: 0 }; 9],
+// Knot 0 is the head, knot N - 1 the tail. A rope without knots does not
+// compile.
+pub struct Rope<const N: usize> {
+    knots: [Point; N],
+    visited: [HashSet<Point>; N],
+}
+
+impl<const N: usize> Default for Rope<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Rope<N> {
+    pub fn new() -> Self {
+        const { assert!(N > 0, "a rope needs at least one knot") };
+        Self {
+            knots: [Point::default(); N],
+            visited: std::array::from_fn(|_| HashSet::from([Point::default()])),
         }
     }
 
-    fn lachenille(&mut self) {
-        for i in 0..9 {
-            let p_before: Point = if i == 0 { self.head } else { self.tails[i - 1] };
-            let p_diff: Point = p_before - self.tails[i];
+    pub fn apply(&mut self, motion: Motion) {
+        let (delta, steps) = motion.step();
+        for _ in 0..steps {
+            self.move_head(delta);
+        }
+    }
 
-            if p_diff.x.abs() > 1 && p_diff.y == 0 {
-                self.tails[i].x += p_diff.x.signum();
-            } else if p_diff.y.abs() > 1 && p_diff.x == 0 {
-                self.tails[i].y += p_diff.y.signum();
-            } else if p_diff.x.abs() + p_diff.y.abs() > 2 {
-                self.tails[i].x += p_diff.x.signum();
-                self.tails[i].y += p_diff.y.signum();
+    fn move_head(&mut self, delta: Point) {
+        self.knots[0] = self.knots[0] + delta;
+        for i in 1..N {
+            let p_diff = self.knots[i - 1] - self.knots[i];
+            if p_diff.x.abs() > 1 || p_diff.y.abs() > 1 {
+                self.knots[i].x += p_diff.x.signum();
+                self.knots[i].y += p_diff.y.signum();
             }
         }
+        for (knot, visited) in self.knots.iter().zip(self.visited.iter_mut()) {
+            visited.insert(*knot);
+        }
+    }
+
+    // Positions of each knot, from the head to the tail.
+    pub fn knots(&self) -> Vec<(isize, isize)> {
+        self.knots.iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    // Sorted positions visited by the given knot, `None` past the tail.
+    pub fn visited(&self, knot: usize) -> Option<Vec<(isize, isize)>> {
+        let visited = self.visited.get(knot)?;
+        Some(visited.iter().map(|p| (p.x, p.y)).sorted().collect())
+    }
+
+    pub fn visited_count(&self, knot: usize) -> Option<usize> {
+        self.visited.get(knot).map(|visited| visited.len())
     }
 
-    fn up(&mut self) {
-        self.head.y += 1;
-        self.lachenille();
+    fn tail_visited(&self) -> &HashSet<Point> {
+        &self.visited[N - 1]
     }
 
-    fn left(&mut self) {
-        self.head.x -= 1;
-        self.lachenille();
+    // `H` for the head, then knots are numbered in base 36 (`1` to `9`, then
+    // `a` to `z`). The tail of a two-knot rope and any knot past `z` are `T`.
+    fn label(knot: usize) -> char {
+        match knot {
+            0 => 'H',
+            k if k == N - 1 && N == 2 => 'T',
+            k => char::from_digit(k as u32, 36).unwrap_or('T'),
+        }
     }
 
-    fn right(&mut self) {
-        self.head.x += 1;
-        self.lachenille();
+    // Draws the rope as in the puzzle text, with `y` growing upwards: knots
+    // closer to the head hide the ones behind them, `s` marks the starting
+    // point and `#` the positions visited by the tail.
+    fn render(&self, (min, max): (Point, Point)) -> String {
+        (min.y..=max.y)
+            .rev()
+            .map(|y| {
+                (min.x..=max.x)
+                    .map(|x| {
+                        let p = Point { x, y };
+                        if let Some(knot) = self.knots.iter().position(|k| *k == p) {
+                            Self::label(knot)
+                        } else if p == Point::default() {
+                            's'
+                        } else if self.tail_visited().contains(&p) {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
     }
 
-    fn down(&mut self) {
-        self.head.y -= 1;
-        self.lachenille();
+    fn bounds(&self) -> (Point, Point) {
+        let points = self.visited.iter().flatten().collect::<Vec<&Point>>();
+        (
+            Point {
+                x: points.iter().map(|p| p.x).min().unwrap_or(0),
+                y: points.iter().map(|p| p.y).min().unwrap_or(0),
+            },
+            Point {
+                x: points.iter().map(|p| p.x).max().unwrap_or(0),
+                y: points.iter().map(|p| p.y).max().unwrap_or(0),
+            },
+        )
     }
 }
 
-fn part_one(input: &str) -> BoxResult<usize> {
-    let mut res: HashSet<Point> = HashSet::new();
-    let mut rope = RopeV1::default();
-    input.lines().for_each(|line| {
-        if let Some((direction, moves)) = line.split_ascii_whitespace().collect_tuple() {
-            let n_moves = moves.parse::<isize>().unwrap();
-            match direction {
-                "R" => {
-                    for _ in 0..n_moves {
-                        rope.right();
-                        res.insert(rope.tail);
-                    }
-                }
-                "U" => {
-                    for _ in 0..n_moves {
-                        rope.up();
-                        res.insert(rope.tail);
-                    }
-                }
-                "L" => {
-                    for _ in 0..n_moves {
-                        rope.left();
-                        res.insert(rope.tail);
-                    }
-                }
-                "D" => {
-                    for _ in 0..n_moves {
-                        rope.down();
-                        res.insert(rope.tail);
-                    }
+// One frame per motion, all drawn on the area covered by the whole simulation.
+// Frames are drawn as the iterator is consumed.
+fn frames<const N: usize>(input: &str) -> BoxResult<impl Iterator<Item = String>> {
+    let bounds = simulate::<N>(input)?.bounds();
+    let mut rope = Rope::<N>::new();
+    Ok(parse_motions(input)?.into_iter().map(move |motion| {
+        rope.apply(motion);
+        format!("== {} ==\n{}\n", motion, rope.render(bounds))
+    }))
+}
+
+pub enum FrameOutput<'a> {
+    Directory(&'a Path),
+    File(&'a Path),
+}
+
+// Each frame is written as soon as it is drawn.
+pub fn write_frames<const N: usize>(input: &str, output: FrameOutput) -> BoxResult<()> {
+    let frames = frames::<N>(input)?;
+    match output {
+        FrameOutput::Directory(dir) => {
+            fs::create_dir_all(dir)?;
+            for (i, frame) in frames.enumerate() {
+                fs::write(dir.join(format!("frame_{:05}.txt", i)), frame)?;
+            }
+        }
+        FrameOutput::File(path) => {
+            let mut file = BufWriter::new(File::create(path)?);
+            for (i, frame) in frames.enumerate() {
+                if i > 0 {
+                    file.write_all(b"\n")?;
                 }
-                _ => println!("{}", AOCError),
+                file.write_all(frame.as_bytes())?;
             }
+            file.flush()?;
         }
-    });
-    Ok(res.len())
+    }
+    Ok(())
+}
+
+// Rope after applying every motion of the input.
+pub fn simulate<const N: usize>(input: &str) -> BoxResult<Rope<N>> {
+    let mut rope = Rope::<N>::new();
+    parse_motions(input)?
+        .into_iter()
+        .for_each(|motion| rope.apply(motion));
+    Ok(rope)
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    Ok(simulate::<2>(input)?.tail_visited().len())
 }
 
 fn part_two(input: &str) -> BoxResult<usize> {
-    let mut res: HashSet<Point> = HashSet::new();
-    let mut rope = RopeV2::default();
-    input.lines().for_each(|line| {
-        if let Some((direction, moves)) = line.split_ascii_whitespace().collect_tuple() {
-            let n_moves = moves.parse::<isize>().unwrap();
-            match direction {
-                "R" => {
-                    for _ in 0..n_moves {
-                        rope.right();
-                        res.insert(rope.tails[8]);
-                    }
-                }
-                "U" => {
-                    for _ in 0..n_moves {
-                        rope.up();
-                        res.insert(rope.tails[8]);
-                    }
-                }
-                "L" => {
-                    for _ in 0..n_moves {
-                        rope.left();
-                        res.insert(rope.tails[8]);
-                    }
-                }
-                "D" => {
-                    for _ in 0..n_moves {
-                        rope.down();
-                        res.insert(rope.tails[8]);
-                    }
-                }
-                _ => println!("{}", AOCError),
-            }
-        }
-    });
-    Ok(res.len())
+    Ok(simulate::<10>(input)?.tail_visited().len())
 }
 
 pub fn run() {
@@ -213,4 +280,62 @@ mod tests {
         let input = &read_file_to_string("./examples/day09bis.txt");
         assert_eq!(part_two(input).ok(), Some(36));
     }
+
+    #[test]
+    fn test_visited_per_knot() {
+        let input = &read_file_to_string("./examples/day09.txt");
+        let rope = simulate::<10>(input).unwrap();
+        assert_eq!(rope.visited_count(1), Some(13));
+        assert_eq!(rope.visited(9), Some(vec![(0, 0)]));
+        assert_eq!(rope.visited_count(10), None);
+        assert_eq!(rope.knots()[0], (2, 2));
+
+        let rope = simulate::<1>(input).unwrap();
+        assert_eq!(rope.visited(0).unwrap().first(), Some(&(0, 0)));
+    }
+
+    #[test]
+    fn test_frames() {
+        let input = &read_file_to_string("./examples/day09.txt");
+        let short = frames::<2>(input).unwrap().collect::<Vec<String>>();
+        assert_eq!(short.len(), 8);
+        assert_eq!(
+            short[0],
+            "== R 4 ==\n......\n......\n......\n......\ns##TH.\n"
+        );
+        assert_eq!(
+            short[7],
+            "== R 2 ==\n..##..\n...##.\n.TH##.\n....#.\ns###..\n"
+        );
+
+        let long = frames::<10>(input).unwrap().collect::<Vec<String>>();
+        assert_eq!(
+            long[1],
+            "== U 4 ==\n....H.\n....1.\n..432.\n.5....\n6.....\n"
+        );
+    }
+
+    #[test]
+    fn test_write_frames() {
+        let input = &read_file_to_string("./examples/day09.txt");
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!(
+            "aoc2022_day09_frames_{}_{}",
+            std::process::id(),
+            nanos
+        ));
+        write_frames::<2>(input, FrameOutput::Directory(&dir)).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 8);
+
+        let file = dir.join("all.txt");
+        write_frames::<2>(input, FrameOutput::File(&file)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            frames::<2>(input).unwrap().join("\n")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
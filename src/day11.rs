@@ -12,7 +12,6 @@ struct Monkey {
     div_by: usize,
     monkey_if_true: usize,
     monkey_if_false: usize,
-    n_checked_items: usize,
 }
 
 impl Monkey {
@@ -24,221 +23,179 @@ impl Monkey {
             div_by: 0,
             monkey_if_true: 0,
             monkey_if_false: 0,
-            n_checked_items: 0,
         }
     }
-}
-
-fn part_one(input: &str) -> BoxResult<usize> {
-    let s_monkeys = input.split("\n\n");
-    let mut monkeys: HashMap<usize, Monkey> = HashMap::new();
 
-    s_monkeys.enumerate().for_each(|(i, s_monkey)| {
-        let mut monkey = Monkey::new();
-        s_monkey.lines().skip(1).for_each(|line| match line {
-            items if line.trim().starts_with("Starting items:") => {
-                monkey.items = items
-                    .split(":")
-                    .last()
-                    .unwrap()
-                    .split(",")
-                    .map(|value| value.trim().parse::<usize>().unwrap())
-                    .collect::<Vec<usize>>();
-            }
-            operation if line.trim().starts_with("Operation:") => {
-                let (value, op) = operation
-                    .split_ascii_whitespace()
-                    .rev()
-                    .take(2)
-                    .collect_tuple()
-                    .unwrap();
-                monkey.op = op.to_string();
-                monkey.op_value = if value == "old" {
-                    0
-                } else {
-                    value.parse::<usize>().unwrap()
-                };
-            }
-            divisible if line.trim().starts_with("Test:") => {
-                monkey.div_by = divisible
-                    .split_ascii_whitespace()
-                    .last()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap();
-            }
-            cond_true if line.trim().starts_with("If true:") => {
-                monkey.monkey_if_true = cond_true
-                    .split_ascii_whitespace()
-                    .last()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap();
-            }
-            cond_false if line.trim().starts_with("If false:") => {
-                monkey.monkey_if_false = cond_false
-                    .split_ascii_whitespace()
-                    .last()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap();
-            }
-            _ => println!("{}", AOCError),
-        });
-        monkeys.insert(i, monkey);
-    });
+    fn inspect(&self, worry: usize) -> usize {
+        let value = if self.op_value == 0 {
+            worry
+        } else {
+            self.op_value
+        };
+        match self.op.as_str() {
+            "+" => worry + value,
+            "*" => worry * value,
+            _ => 0,
+        }
+    }
 
-    for _ in 0..20 {
-        for i in 0..monkeys.len() {
-            monkeys[&i].clone().items.iter().for_each(|v| {
-                let res = match monkeys[&i].op.as_str() {
-                    "+" => {
-                        v + (if monkeys[&i].op_value == 0 {
-                            v
-                        } else {
-                            &monkeys[&i].op_value
-                        })
-                    }
-                    "*" => {
-                        v * (if monkeys[&i].op_value == 0 {
-                            v
-                        } else {
-                            &monkeys[&i].op_value
-                        })
-                    }
-                    _ => 0,
-                };
-                let it = res / 3;
-                if it % monkeys[&i].div_by == 0 {
-                    monkeys
-                        .entry(monkeys[&i].monkey_if_true)
-                        .and_modify(|m| m.items.push(it));
-                } else {
-                    monkeys
-                        .entry(monkeys[&i].monkey_if_false)
-                        .and_modify(|m| m.items.push(it));
-                };
-            });
-            monkeys.entry(i).and_modify(|m| {
-                m.n_checked_items += m.items.len();
-                m.items.clear();
-            });
+    fn throw_to(&self, worry: usize) -> usize {
+        if worry.is_multiple_of(self.div_by) {
+            self.monkey_if_true
+        } else {
+            self.monkey_if_false
         }
     }
-    let mut total_items_checked = monkeys
-        .iter()
-        .map(|(_, monkey)| monkey.n_checked_items)
-        .collect::<Vec<usize>>();
-    total_items_checked.sort();
-    total_items_checked.reverse();
-    Ok(total_items_checked.iter().take(2).product())
 }
 
-fn part_two(input: &str) -> BoxResult<usize> {
-    let s_monkeys = input.split("\n\n");
-    let mut monkeys: HashMap<usize, Monkey> = HashMap::new();
+fn parse_monkeys(input: &str) -> Vec<Monkey> {
+    input
+        .split("\n\n")
+        .map(|s_monkey| {
+            let mut monkey = Monkey::new();
+            s_monkey.lines().skip(1).for_each(|line| match line {
+                items if line.trim().starts_with("Starting items:") => {
+                    monkey.items = items
+                        .split(':')
+                        .next_back()
+                        .unwrap()
+                        .split(',')
+                        .map(|value| value.trim().parse::<usize>().unwrap())
+                        .collect::<Vec<usize>>();
+                }
+                operation if line.trim().starts_with("Operation:") => {
+                    let (value, op) = operation
+                        .split_ascii_whitespace()
+                        .rev()
+                        .take(2)
+                        .collect_tuple()
+                        .unwrap();
+                    monkey.op = op.to_string();
+                    monkey.op_value = if value == "old" {
+                        0
+                    } else {
+                        value.parse::<usize>().unwrap()
+                    };
+                }
+                divisible if line.trim().starts_with("Test:") => {
+                    monkey.div_by = divisible
+                        .split_ascii_whitespace()
+                        .last()
+                        .unwrap()
+                        .parse::<usize>()
+                        .unwrap();
+                }
+                cond_true if line.trim().starts_with("If true:") => {
+                    monkey.monkey_if_true = cond_true
+                        .split_ascii_whitespace()
+                        .last()
+                        .unwrap()
+                        .parse::<usize>()
+                        .unwrap();
+                }
+                cond_false if line.trim().starts_with("If false:") => {
+                    monkey.monkey_if_false = cond_false
+                        .split_ascii_whitespace()
+                        .last()
+                        .unwrap()
+                        .parse::<usize>()
+                        .unwrap();
+                }
+                _ => println!("{}", AOCError),
+            });
+            monkey
+        })
+        .collect()
+}
 
-    s_monkeys.enumerate().for_each(|(i, s_monkey)| {
-        let mut monkey = Monkey::new();
-        s_monkey.lines().skip(1).for_each(|line| match line {
-            items if line.trim().starts_with("Starting items:") => {
-                monkey.items = items
-                    .split(":")
-                    .last()
-                    .unwrap()
-                    .split(",")
-                    .map(|value| value.trim().parse::<usize>().unwrap())
-                    .collect::<Vec<usize>>();
-            }
-            operation if line.trim().starts_with("Operation:") => {
-                let (value, op) = operation
-                    .split_ascii_whitespace()
-                    .rev()
-                    .take(2)
-                    .collect_tuple()
-                    .unwrap();
-                monkey.op = op.to_string();
-                monkey.op_value = if value == "old" {
-                    0
-                } else {
-                    value.parse::<usize>().unwrap()
-                };
-            }
-            divisible if line.trim().starts_with("Test:") => {
-                monkey.div_by = divisible
-                    .split_ascii_whitespace()
-                    .last()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap();
-            }
-            cond_true if line.trim().starts_with("If true:") => {
-                monkey.monkey_if_true = cond_true
-                    .split_ascii_whitespace()
-                    .last()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap();
-            }
-            cond_false if line.trim().starts_with("If false:") => {
-                monkey.monkey_if_false = cond_false
-                    .split_ascii_whitespace()
-                    .last()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap();
-            }
-            _ => println!("{}", AOCError),
-        });
-        monkeys.insert(i, monkey);
-    });
+// State of an item at the start of a round: the monkey holding it and its worry level.
+type ItemState = (usize, usize);
 
-    let pgcd: usize = monkeys.values().map(|m| m.div_by).product();
+// Items never interact with each other, so each one can be followed on its own
+// through the rounds. A round ends for an item as soon as it is thrown to a
+// monkey that has already played (or to the one currently playing).
+fn step_round(
+    monkeys: &[Monkey],
+    relief: &impl Fn(usize) -> usize,
+    (mut monkey, mut worry): ItemState,
+    inspections: &mut [usize],
+) -> ItemState {
+    loop {
+        inspections[monkey] += 1;
+        worry = relief(monkeys[monkey].inspect(worry));
+        let target = monkeys[monkey].throw_to(worry);
+        if target <= monkey {
+            return (target, worry);
+        }
+        monkey = target;
+    }
+}
 
-    for _ in 0..10000 {
-        for i in 0..monkeys.len() {
-            monkeys[&i].clone().items.iter().for_each(|v| {
-                let res = match monkeys[&i].op.as_str() {
-                    "+" => {
-                        v + (if monkeys[&i].op_value == 0 {
-                            v
-                        } else {
-                            &monkeys[&i].op_value
-                        })
-                    }
-                    "*" => {
-                        v * (if monkeys[&i].op_value == 0 {
-                            v
-                        } else {
-                            &monkeys[&i].op_value
-                        })
-                    }
-                    _ => 0,
-                };
-                let it = res % pgcd;
-                if it % monkeys[&i].div_by == 0 {
-                    monkeys
-                        .entry(monkeys[&i].monkey_if_true)
-                        .and_modify(|m| m.items.push(it));
-                } else {
-                    monkeys
-                        .entry(monkeys[&i].monkey_if_false)
-                        .and_modify(|m| m.items.push(it));
-                };
-            });
-            monkeys.entry(i).and_modify(|m| {
-                m.n_checked_items += m.items.len();
-                m.items.clear();
-            });
+// Number of inspections of a single item by each monkey after `rounds` rounds.
+// Once the item comes back to a state already seen at the start of a round,
+// its trajectory is periodic and the remaining rounds are extrapolated.
+fn item_inspections(
+    monkeys: &[Monkey],
+    relief: &impl Fn(usize) -> usize,
+    start: ItemState,
+    rounds: usize,
+) -> Vec<usize> {
+    let mut seen: HashMap<ItemState, usize> = HashMap::new();
+    let mut history: Vec<Vec<usize>> = vec![vec![0; monkeys.len()]];
+    let mut state = start;
+
+    for round in 0..rounds {
+        if let Some(&cycle_start) = seen.get(&state) {
+            let cycle_len = round - cycle_start;
+            let remaining = rounds - round;
+            let (n_cycles, rest) = (remaining / cycle_len, remaining % cycle_len);
+            let (first, current) = (&history[cycle_start], &history[round]);
+            let partial = &history[cycle_start + rest];
+            return (0..monkeys.len())
+                .map(|i| current[i] + n_cycles * (current[i] - first[i]) + partial[i] - first[i])
+                .collect();
         }
+        seen.insert(state, round);
+
+        let mut inspections = history[round].clone();
+        state = step_round(monkeys, relief, state, &mut inspections);
+        history.push(inspections);
     }
-    let mut total_items_checked = monkeys
+
+    history.pop().unwrap()
+}
+
+fn inspections(monkeys: &[Monkey], relief: impl Fn(usize) -> usize, rounds: usize) -> Vec<usize> {
+    monkeys
         .iter()
-        .map(|(_, monkey)| monkey.n_checked_items)
-        .collect::<Vec<usize>>();
-    total_items_checked.sort();
-    total_items_checked.reverse();
-    Ok(total_items_checked.iter().take(2).product())
+        .enumerate()
+        .flat_map(|(i, monkey)| monkey.items.iter().map(move |&worry| (i, worry)))
+        .map(|start| item_inspections(monkeys, &relief, start, rounds))
+        .fold(vec![0; monkeys.len()], |mut acc, counts| {
+            acc.iter_mut().zip(counts).for_each(|(a, c)| *a += c);
+            acc
+        })
+}
+
+fn monkey_business(inspections: &[usize]) -> usize {
+    inspections.iter().sorted().rev().take(2).product()
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    let monkeys = parse_monkeys(input);
+    Ok(monkey_business(&inspections(&monkeys, |w| w / 3, 20)))
+}
+
+// Inspections by each monkey after `rounds` rounds without relief, in time
+// independent of `rounds`.
+pub fn inspection_counts(input: &str, rounds: usize) -> BoxResult<Vec<usize>> {
+    let monkeys = parse_monkeys(input);
+    let pgcd: usize = monkeys.iter().map(|m| m.div_by).product();
+    Ok(inspections(&monkeys, |w| w % pgcd, rounds))
+}
+
+fn part_two(input: &str) -> BoxResult<usize> {
+    Ok(monkey_business(&inspection_counts(input, 10000)?))
 }
 
 pub fn run() {
@@ -263,4 +220,52 @@ mod tests {
         let input = &read_file_to_string("./examples/day11.txt");
         assert_eq!(part_two(input).ok(), Some(2713310158));
     }
+
+    // Plays every round with all the items, without extrapolation.
+    fn simulate(input: &str, rounds: usize) -> Vec<usize> {
+        let mut monkeys = parse_monkeys(input);
+        let pgcd: usize = monkeys.iter().map(|m| m.div_by).product();
+        let mut res = vec![0; monkeys.len()];
+        for _ in 0..rounds {
+            for i in 0..monkeys.len() {
+                for worry in std::mem::take(&mut monkeys[i].items) {
+                    res[i] += 1;
+                    let worry = monkeys[i].inspect(worry) % pgcd;
+                    let target = monkeys[i].throw_to(worry);
+                    monkeys[target].items.push(worry);
+                }
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_inspection_counts() {
+        let input = &read_file_to_string("./examples/day11.txt");
+        for rounds in [0, 1, 20, 1001, 20000, 23457] {
+            assert_eq!(
+                inspection_counts(input, rounds).ok(),
+                Some(simulate(input, rounds))
+            );
+        }
+        assert_eq!(
+            inspection_counts(input, 1_000_000_000).ok(),
+            Some(vec![5217653496, 4782346500, 193256586, 5202028499])
+        );
+    }
+
+    #[test]
+    fn test_inspections() {
+        let input = &read_file_to_string("./examples/day11.txt");
+        let monkeys = parse_monkeys(input);
+        let pgcd: usize = monkeys.iter().map(|m| m.div_by).product();
+        assert_eq!(
+            inspections(&monkeys, |w| w % pgcd, 1000),
+            vec![5204, 4792, 199, 5192]
+        );
+        assert_eq!(
+            inspections(&monkeys, |w| w % pgcd, 10000),
+            vec![52166, 47830, 1938, 52013]
+        );
+    }
 }
@@ -2,6 +2,8 @@ use std::collections::BinaryHeap;
 use std::ops::Add;
 use std::{cmp::Ordering, collections::BTreeMap};
 
+use itertools::Itertools;
+
 use crate::{read_file_to_string, solve, AOCError, BoxResult};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -73,24 +75,29 @@ impl PartialOrd for State {
     }
 }
 
-// Dijkstra's shortest path algorithm.
+// Dijkstra's shortest path algorithm, returning the route from start to end.
 
-fn shortest_path(graph: &BTreeMap<Point, Node>, start: &Point, end: &Point) -> Option<usize> {
+fn shortest_path(graph: &BTreeMap<Point, Node>, start: &Point, end: &Point) -> Option<Vec<Point>> {
     let mut dist: BTreeMap<Point, usize> =
-        BTreeMap::from_iter(graph.keys().map(|p| (p.clone(), usize::MAX)));
+        BTreeMap::from_iter(graph.keys().map(|p| (*p, usize::MAX)));
+    let mut prev: BTreeMap<Point, Point> = BTreeMap::new();
 
     let mut heap: BinaryHeap<State> = BinaryHeap::new();
 
-    dist.entry(start.clone()).and_modify(|c| *c = 0);
+    dist.entry(*start).and_modify(|c| *c = 0);
     heap.push(State {
-        point: start.clone(),
+        point: *start,
         cost: 0,
     });
 
     while let Some(State { point, cost }) = heap.pop() {
-        // println!("{:?}", dist);
         if point == *end {
-            return Some(cost);
+            let mut path = vec![point];
+            while let Some(p) = prev.get(path.last().unwrap()) {
+                path.push(*p);
+            }
+            path.reverse();
+            return Some(path);
         }
 
         if cost > *dist.get(&point).unwrap() {
@@ -110,6 +117,7 @@ fn shortest_path(graph: &BTreeMap<Point, Node>, start: &Point, end: &Point) -> O
                             dist.entry(next.point)
                                 .and_modify(|c| *c = next.cost)
                                 .or_insert(next.cost);
+                            prev.insert(next.point, point);
                         }
                     }
                 }
@@ -120,48 +128,157 @@ fn shortest_path(graph: &BTreeMap<Point, Node>, start: &Point, end: &Point) -> O
     None
 }
 
-fn build_graph(input: &str) -> (BTreeMap<Point, Node>, Point, Point) {
+// Draws the heightmap with the route as in the puzzle text: every step shows
+// the direction of the next one and the goal is marked with `E`.
+fn render_path(graph: &BTreeMap<Point, Node>, path: &[Point]) -> String {
+    let width = graph.keys().map(|p| p.x).max().unwrap_or(-1) + 1;
+    let height = graph.keys().map(|p| p.y).max().unwrap_or(-1) + 1;
+    let mut grid = vec![vec!['.'; width as usize]; height as usize];
+
+    path.iter().tuple_windows().for_each(|(from, to)| {
+        grid[from.y as usize][from.x as usize] = match (to.x - from.x, to.y - from.y) {
+            (1, 0) => '>',
+            (-1, 0) => '<',
+            (0, -1) => '^',
+            _ => 'v',
+        };
+    });
+    if let Some(end) = path.last() {
+        grid[end.y as usize][end.x as usize] = 'E';
+    }
+
+    grid.iter()
+        .map(|row| row.iter().collect::<String>())
+        .join("\n")
+}
+
+pub fn render_route(input: &str) -> BoxResult<String> {
+    let (graph, start, end) = build_graph(input)?;
+    let path = shortest_path(&graph, &start, &end).ok_or(AOCError)?;
+    Ok(render_path(&graph, &path))
+}
+
+pub fn route_coordinates(input: &str) -> BoxResult<Vec<(isize, isize)>> {
+    let (graph, start, end) = build_graph(input)?;
+    let path = shortest_path(&graph, &start, &end).ok_or(AOCError)?;
+    Ok(path.iter().map(|p| (p.x, p.y)).collect())
+}
+
+// Same search run backwards from the goal with the climbing rule inverted: a
+// step down is allowed if it is at most one level lower. This gives the
+// distance from every reachable cell to the goal in a single pass.
+fn distances_to(graph: &BTreeMap<Point, Node>, end: &Point) -> BTreeMap<Point, usize> {
+    let mut dist: BTreeMap<Point, usize> = BTreeMap::new();
+    let mut heap: BinaryHeap<State> = BinaryHeap::new();
+
+    dist.insert(*end, 0);
+    heap.push(State {
+        point: *end,
+        cost: 0,
+    });
+
+    while let Some(State { point, cost }) = heap.pop() {
+        if cost > *dist.get(&point).unwrap() {
+            continue;
+        }
+        let pos = &graph[&point];
+        for edge in pos.point.neighbors() {
+            if let Some(neighbor) = graph.get(&edge) {
+                if neighbor.cost as isize - pos.cost as isize >= -1 {
+                    let next = State {
+                        point: neighbor.point,
+                        cost: cost + 1,
+                    };
+
+                    if next.cost < *dist.get(&next.point).unwrap_or(&usize::MAX) {
+                        heap.push(next);
+                        dist.insert(next.point, next.cost);
+                    }
+                }
+            }
+        }
+    }
+
+    dist
+}
+
+pub struct DistanceMap {
+    graph: BTreeMap<Point, Node>,
+    start: Point,
+    distances: BTreeMap<Point, usize>,
+}
+
+impl DistanceMap {
+    pub fn new(input: &str) -> BoxResult<Self> {
+        let (graph, start, end) = build_graph(input)?;
+        let distances = distances_to(&graph, &end);
+        Ok(Self {
+            graph,
+            start,
+            distances,
+        })
+    }
+
+    pub fn distance(&self, x: isize, y: isize) -> Option<usize> {
+        self.distances.get(&Point::new(x, y)).copied()
+    }
+
+    pub fn from_start(&self) -> Option<usize> {
+        self.distances.get(&self.start).copied()
+    }
+
+    // Closest cell of the given elevation (`a` to `z`) and its distance to the
+    // goal, `None` for any other character.
+    pub fn closest(&self, elevation: char) -> Option<((isize, isize), usize)> {
+        if !elevation.is_ascii_lowercase() {
+            return None;
+        }
+        self.graph
+            .values()
+            .filter(|n| n.cost == elevation as usize - 97)
+            .filter_map(|n| Some(((n.point.x, n.point.y), self.distances.get(&n.point)?)))
+            .min_by_key(|(_, d)| **d)
+            .map(|(p, d)| (p, *d))
+    }
+}
+
+// Fails on any cell other than `a` to `z`, `S` and `E`, or without exactly
+// one `S` and one `E`.
+fn build_graph(input: &str) -> Result<(BTreeMap<Point, Node>, Point, Point), AOCError> {
     let mut start: Option<Point> = None;
     let mut end: Option<Point> = None;
     let mut graph: BTreeMap<Point, Node> = BTreeMap::new();
 
-    input.lines().enumerate().for_each(|(n_line, line)| {
-        line.chars().enumerate().for_each(|(n_char, char)| {
+    for (n_line, line) in input.lines().enumerate() {
+        for (n_char, char) in line.chars().enumerate() {
             let p = Point::new(n_char as isize, n_line as isize);
-            match char {
-                'S' => {
+            let elevation = match char {
+                'S' if start.is_none() => {
                     start = Some(p);
-                    graph.entry(p).or_insert(Node::new(p, 'a' as usize - 97));
+                    'a'
                 }
-                'E' => {
+                'E' if end.is_none() => {
                     end = Some(p);
-                    graph.entry(p).or_insert(Node::new(p, 'z' as usize - 97));
-                }
-                c if c.is_ascii_alphabetic() => {
-                    graph.entry(p).or_insert(Node::new(p, char as usize - 97));
+                    'z'
                 }
-                _ => println!("{}", AOCError),
-            }
-        })
-    });
+                c if c.is_ascii_lowercase() => c,
+                _ => return Err(AOCError),
+            };
+            graph.insert(p, Node::new(p, elevation as usize - 97));
+        }
+    }
 
-    (graph, start.unwrap(), end.unwrap())
+    Ok((graph, start.ok_or(AOCError)?, end.ok_or(AOCError)?))
 }
 
 fn part_one(input: &str) -> BoxResult<usize> {
-    let (graph, start, end) = build_graph(input);
-    let res = shortest_path(&graph, &start, &end);
-    Ok(res.unwrap())
+    let res = DistanceMap::new(input)?.from_start().ok_or(AOCError)?;
+    Ok(res)
 }
 
 fn part_two(input: &str) -> BoxResult<usize> {
-    let (graph, _start, end) = build_graph(input);
-    let res = graph
-        .values()
-        .filter(|n| n.cost == 0)
-        .filter_map(|p| shortest_path(&graph, &p.point, &end))
-        .min();
-    Ok(res.unwrap())
+    let (_, res) = DistanceMap::new(input)?.closest('a').ok_or(AOCError)?;
+    Ok(res)
 }
 
 pub fn run() {
@@ -186,4 +303,44 @@ mod tests {
         let input = &read_file_to_string("./examples/day12.txt");
         assert_eq!(part_two(input).ok(), Some(29));
     }
+
+    #[test]
+    fn test_render_path() {
+        let input = &read_file_to_string("./examples/day12.txt");
+        assert_eq!(
+            render_route(input).ok(),
+            Some([">>vv<<<<", "..vvv<<^", "..vv>E^^", "..v>>>^^", "..>>>>>^"].join("\n"))
+        );
+        let route = route_coordinates(input).unwrap();
+        assert_eq!(route.len(), 32);
+        assert_eq!(route.first(), Some(&(0, 0)));
+        assert_eq!(route.last(), Some(&(5, 2)));
+    }
+
+    #[test]
+    fn test_distance_map() {
+        let input = &read_file_to_string("./examples/day12.txt");
+        let map = DistanceMap::new(input).unwrap();
+        assert_eq!(map.distance(5, 2), Some(0));
+        assert_eq!(map.from_start(), Some(31));
+        assert_eq!(map.closest('a'), Some(((0, 4), 29)));
+        assert_eq!(map.closest('y'), Some(((4, 1), 2)));
+        assert_eq!(map.closest('S'), None);
+        assert_eq!(map.closest('{'), None);
+    }
+
+    #[test]
+    fn test_invalid_map() {
+        for input in [
+            "Sabc\nabcE\nAbcd",
+            "Sab\nbcd",
+            "abE\nbcd",
+            "SaE\nSbc",
+            "Sa.\nbcE",
+        ] {
+            assert!(DistanceMap::new(input).is_err());
+            assert!(render_route(input).is_err());
+            assert!(route_coordinates(input).is_err());
+        }
+    }
 }
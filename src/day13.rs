@@ -1,119 +1,451 @@
-use crate::{read_file_to_string, solve, AOCError, BoxResult};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
 
-#[derive(Clone, Debug, Ord, Eq, PartialEq)]
-enum Signal {
+use serde_json::Value;
+
+use crate::{read_file_to_buffer, read_file_to_string, solve, AOCError, BoxResult};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Signal {
     Value(usize),
     List(Vec<Signal>),
 }
 
-impl From<String> for Signal {
-    fn from(input: String) -> Self {
-        let mut chars = input.chars().fuse();
-        let mut packet: Vec<Signal> = vec![];
-        let mut current_number = String::new();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSignalError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl ParseSignalError {
+    fn new(position: usize, message: &str) -> Self {
+        Self {
+            position,
+            message: message.to_string(),
+        }
+    }
+}
 
-        while let Some(c) = chars.next() {
-            match c {
-                '[' => packet.push(Signal::List(Vec::new())),
-                ']' => {
-                    if !current_number.is_empty() {
-                        let value = current_number.parse::<usize>().unwrap();
+impl fmt::Display for ParseSignalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid packet at position {}: {}",
+            self.position, self.message
+        )
+    }
+}
 
-                        current_number.clear();
+impl Error for ParseSignalError {}
 
-                        match packet.last_mut().unwrap() {
-                            Signal::List(v) => {
-                                v.push(Signal::Value(value));
-                            }
-                            _ => println!("{}", AOCError),
-                        }
-                    }
-                    if packet.len() > 1 {
-                        let sig = packet.pop().unwrap();
-                        match packet.last_mut().unwrap() {
-                            Signal::List(v) => v.push(sig),
-                            _ => println!("{}", AOCError),
-                        }
+fn parse_signal(bytes: &[u8], pos: &mut usize) -> Result<Signal, ParseSignalError> {
+    match bytes.get(*pos) {
+        Some(b'[') => {
+            *pos += 1;
+            let mut list = vec![];
+            if bytes.get(*pos) == Some(&b']') {
+                *pos += 1;
+                return Ok(Signal::List(list));
+            }
+            loop {
+                list.push(parse_signal(bytes, pos)?);
+                match bytes.get(*pos) {
+                    Some(b',') => *pos += 1,
+                    Some(b']') => {
+                        *pos += 1;
+                        return Ok(Signal::List(list));
                     }
+                    Some(_) => return Err(ParseSignalError::new(*pos, "expected `,` or `]`")),
+                    None => return Err(ParseSignalError::new(*pos, "unexpected end of packet")),
                 }
-                ',' => {
-                    if !current_number.is_empty() {
-                        let value = current_number.parse::<usize>().unwrap();
-
-                        current_number.clear();
-
-                        match packet.last_mut().unwrap() {
-                            Signal::List(v) => {
-                                v.push(Signal::Value(value));
-                            }
-                            _ => println!("{}", AOCError),
-                        }
+            }
+        }
+        Some(c) if c.is_ascii_digit() => {
+            let start = *pos;
+            while bytes.get(*pos).is_some_and(|c| c.is_ascii_digit()) {
+                *pos += 1;
+            }
+            std::str::from_utf8(&bytes[start..*pos])
+                .unwrap()
+                .parse::<usize>()
+                .map(Signal::Value)
+                .map_err(|_| ParseSignalError::new(start, "number too large"))
+        }
+        Some(_) => Err(ParseSignalError::new(*pos, "expected `[` or a digit")),
+        None => Err(ParseSignalError::new(*pos, "unexpected end of packet")),
+    }
+}
+
+impl FromStr for Signal {
+    type Err = ParseSignalError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let bytes = input.as_bytes();
+        let mut pos = 0;
+        let signal = parse_signal(bytes, &mut pos)?;
+        if pos != bytes.len() {
+            return Err(ParseSignalError::new(pos, "unexpected trailing characters"));
+        }
+        Ok(signal)
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Signal::Value(v) => write!(f, "{}", v),
+            Signal::List(l) => {
+                write!(f, "[")?;
+                for (i, signal) in l.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
                     }
+                    write!(f, "{}", signal)?;
                 }
-                x if x.is_digit(10) => {
-                    current_number.push(x);
-                }
-                _ => println!("{}", AOCError),
+                write!(f, "]")
             }
         }
+    }
+}
 
-        packet.pop().unwrap()
+impl From<&Signal> for Value {
+    fn from(signal: &Signal) -> Self {
+        match signal {
+            Signal::Value(v) => Value::from(*v),
+            Signal::List(l) => Value::Array(l.iter().map(Value::from).collect()),
+        }
     }
 }
 
-impl PartialOrd for Signal {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+impl TryFrom<&Value> for Signal {
+    type Error = AOCError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => n
+                .as_u64()
+                .map(|v| Signal::Value(v as usize))
+                .ok_or(AOCError),
+            Value::Array(l) => Ok(Signal::List(
+                l.iter().map(Signal::try_from).collect::<Result<_, _>>()?,
+            )),
+            _ => Err(AOCError),
+        }
+    }
+}
+
+fn cmp_lists(a: &[Signal], b: &[Signal], cmp: fn(&Signal, &Signal) -> Ordering) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| cmp(x, y))
+        .find(|o| o.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+impl Signal {
+    // Order of the puzzle: a value compares as a list holding only that value,
+    // so `2` and `[[2]]` are in the same place even though they are written
+    // differently.
+    pub fn packet_cmp(&self, other: &Self) -> Ordering {
+        use Signal::*;
+        match (self, other) {
+            (Value(a), Value(b)) => a.cmp(b),
+            (List(a), List(b)) => cmp_lists(a, b, Signal::packet_cmp),
+            (Value(_), List(b)) => cmp_lists(std::slice::from_ref(self), b, Signal::packet_cmp),
+            (List(a), Value(_)) => cmp_lists(a, std::slice::from_ref(other), Signal::packet_cmp),
+        }
+    }
+
+    // Values before lists, then as written.
+    fn structural_cmp(&self, other: &Self) -> Ordering {
         use Signal::*;
         match (self, other) {
-            (Value(a), Value(b)) => a.partial_cmp(b),
-            (List(a), List(b)) => a.partial_cmp(b),
-            (Value(a), List(..)) => List(vec![Value(*a)]).partial_cmp(other),
-            (List(..), Value(b)) => self.partial_cmp(&List(vec![Value(*b)])),
+            (Value(a), Value(b)) => a.cmp(b),
+            (Value(_), List(_)) => Ordering::Less,
+            (List(_), Value(_)) => Ordering::Greater,
+            (List(a), List(b)) => cmp_lists(a, b, Signal::structural_cmp),
         }
     }
 }
 
-fn part_one(input: &str) -> BoxResult<usize> {
-    let res = input
-        .split("\n\n")
-        .enumerate()
-        .map(|(i, packets)| {
-            if let Some((packet_1, packet_2)) = packets.split_once("\n") {
-                let packet_1 = Signal::from(packet_1.to_string());
-                let packet_2 = Signal::from(packet_2.to_string());
-                if packet_1 < packet_2 {
-                    i + 1
-                } else {
-                    0
+// Packet order, ties between packets written differently being broken by their
+// structure so that equality stays structural.
+impl Ord for Signal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.packet_cmp(other)
+            .then_with(|| self.structural_cmp(other))
+    }
+}
+
+impl PartialOrd for Signal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub fn sort_packets(packets: &mut [Signal]) {
+    packets.sort();
+}
+
+// Sorts the packets together with the dividers and returns the 1-based
+// position of each divider in the resulting list. Dividers are tagged so that
+// an input packet equal to one of them is never mistaken for it.
+pub fn locate_dividers(packets: &[Signal], dividers: &[Signal]) -> Vec<usize> {
+    let mut signals = packets
+        .iter()
+        .map(|signal| (signal, None))
+        .chain(dividers.iter().enumerate().map(|(i, d)| (d, Some(i))))
+        .collect::<Vec<(&Signal, Option<usize>)>>();
+    signals.sort();
+
+    let mut positions = vec![0; dividers.len()];
+    for (position, (_, tag)) in signals.iter().enumerate() {
+        if let Some(i) = tag {
+            positions[*i] = position + 1;
+        }
+    }
+    positions
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Value(usize),
+}
+
+// What the packet text may hold next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Expect {
+    Element,
+    ElementOrClose,
+    SeparatorOrClose,
+    End,
+}
+
+// Reads a packet one token at a time, checking the grammar as it goes with
+// only the current nesting depth. A value compared against a list is put back
+// wrapped in lists of its own, which only needs the value and the number of
+// `]` still owed.
+struct Tokens<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+    expect: Expect,
+    wrapped: Option<usize>,
+    closes: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(packet: &'a str) -> Self {
+        Tokens {
+            bytes: packet.as_bytes(),
+            pos: 0,
+            depth: 0,
+            expect: Expect::Element,
+            wrapped: None,
+            closes: 0,
+        }
+    }
+
+    // Only called right after `value` was returned, so nothing but closing
+    // brackets can be pending at that point.
+    fn wrap(&mut self, value: usize) {
+        self.wrapped = Some(value);
+        self.closes += 1;
+    }
+
+    fn after_element(&self) -> Expect {
+        if self.depth == 0 {
+            Expect::End
+        } else {
+            Expect::SeparatorOrClose
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Token>, ParseSignalError> {
+        if let Some(value) = self.wrapped.take() {
+            return Ok(Some(Token::Value(value)));
+        }
+        if self.closes > 0 {
+            self.closes -= 1;
+            return Ok(Some(Token::Close));
+        }
+        use Expect::*;
+        loop {
+            let byte = self.bytes.get(self.pos).copied();
+            match (self.expect, byte) {
+                (Element | ElementOrClose, Some(b'[')) => {
+                    self.pos += 1;
+                    self.depth += 1;
+                    self.expect = ElementOrClose;
+                    return Ok(Some(Token::Open));
+                }
+                (Element | ElementOrClose, Some(c)) if c.is_ascii_digit() => {
+                    let start = self.pos;
+                    let mut value: usize = 0;
+                    while let Some(c) = self.bytes.get(self.pos).filter(|c| c.is_ascii_digit()) {
+                        value = value
+                            .checked_mul(10)
+                            .and_then(|v| v.checked_add((c - b'0') as usize))
+                            .ok_or_else(|| ParseSignalError::new(start, "number too large"))?;
+                        self.pos += 1;
+                    }
+                    self.expect = self.after_element();
+                    return Ok(Some(Token::Value(value)));
+                }
+                (ElementOrClose | SeparatorOrClose, Some(b']')) => {
+                    self.pos += 1;
+                    self.depth -= 1;
+                    self.expect = self.after_element();
+                    return Ok(Some(Token::Close));
+                }
+                (SeparatorOrClose, Some(b',')) => {
+                    self.pos += 1;
+                    self.expect = Element;
+                }
+                (End, None) => return Ok(None),
+                (_, None) => {
+                    return Err(ParseSignalError::new(self.pos, "unexpected end of packet"))
+                }
+                (Element, Some(_)) => {
+                    return Err(ParseSignalError::new(self.pos, "expected `[` or a digit"))
                 }
-            } else {
-                0
+                (ElementOrClose, Some(_)) => {
+                    return Err(ParseSignalError::new(
+                        self.pos,
+                        "expected `[`, `]` or a digit",
+                    ))
+                }
+                (SeparatorOrClose, Some(_)) => {
+                    return Err(ParseSignalError::new(self.pos, "expected `,` or `]`"))
+                }
+                (End, Some(_)) => {
+                    return Err(ParseSignalError::new(
+                        self.pos,
+                        "unexpected trailing characters",
+                    ))
+                }
+            }
+        }
+    }
+
+    // Checks the rest of the packet once the order is known.
+    fn finish(&mut self) -> Result<(), ParseSignalError> {
+        while self.next()?.is_some() {}
+        Ok(())
+    }
+}
+
+// Compares two packets as `Signal::packet_cmp` does without building them.
+// The order is decided at the first differing token, but both packets are
+// still read to the end so that malformed ones are reported.
+pub fn compare_packets(left: &str, right: &str) -> Result<Ordering, ParseSignalError> {
+    use Token::*;
+    let (mut l, mut r) = (Tokens::new(left), Tokens::new(right));
+    let ordering = loop {
+        match (l.next()?, r.next()?) {
+            (None, None) => break Ordering::Equal,
+            (None, Some(_)) => break Ordering::Less,
+            (Some(_), None) => break Ordering::Greater,
+            (Some(a), Some(b)) => match (a, b) {
+                (Open, Open) | (Close, Close) => {}
+                (Value(a), Value(b)) if a == b => {}
+                (Value(a), Value(b)) => break a.cmp(&b),
+                (Close, _) => break Ordering::Less,
+                (_, Close) => break Ordering::Greater,
+                (Value(a), Open) => l.wrap(a),
+                (Open, Value(b)) => r.wrap(b),
+            },
+        }
+    };
+    l.finish()?;
+    r.finish()?;
+    Ok(ordering)
+}
+
+// Ordering of each pair of packets in the reader, holding no more than the two
+// lines of the current pair in memory.
+pub struct PairChecker<R> {
+    reader: R,
+    left: String,
+    right: String,
+}
+
+impl<R: BufRead> PairChecker<R> {
+    pub fn new(reader: R) -> Self {
+        PairChecker {
+            reader,
+            left: String::new(),
+            right: String::new(),
+        }
+    }
+
+    // Reads the next non blank line into `line`, false at the end of input.
+    fn read_packet(reader: &mut R, line: &mut String) -> BoxResult<bool> {
+        loop {
+            line.clear();
+            if reader.read_line(line)? == 0 {
+                return Ok(false);
             }
-        })
-        .sum();
+            if !line.trim().is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn next_pair(&mut self) -> BoxResult<Option<Ordering>> {
+        if !Self::read_packet(&mut self.reader, &mut self.left)? {
+            return Ok(None);
+        }
+        if !Self::read_packet(&mut self.reader, &mut self.right)? {
+            return Err(Box::new(AOCError));
+        }
+        Ok(Some(compare_packets(self.left.trim(), self.right.trim())?))
+    }
+}
+
+impl<R: BufRead> Iterator for PairChecker<R> {
+    type Item = BoxResult<Ordering>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_pair().transpose()
+    }
+}
+
+// Sum of the 1-based indices of the pairs that are in the right order.
+pub fn sum_ordered_pairs<R: BufRead>(reader: R) -> BoxResult<usize> {
+    let mut res = 0;
+    for (i, ordering) in PairChecker::new(reader).enumerate() {
+        if ordering? == Ordering::Less {
+            res += i + 1;
+        }
+    }
     Ok(res)
 }
 
+pub fn sum_ordered_pairs_in_file<P: AsRef<Path>>(path: P) -> BoxResult<usize> {
+    sum_ordered_pairs(read_file_to_buffer(path)?)
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    sum_ordered_pairs(input.as_bytes())
+}
+
 fn part_two(input: &str) -> BoxResult<usize> {
-    let dividers = vec![
-        Signal::from(String::from("[[2]]")),
-        Signal::from(String::from("[[6]]")),
-    ];
+    let dividers = ["[[2]]".parse::<Signal>()?, "[[6]]".parse::<Signal>()?];
 
-    let mut signals = input
+    let signals = input
         .lines()
         .filter(|line| !line.is_empty())
-        .map(|line| Signal::from(line.to_string()))
-        .collect::<Vec<Signal>>();
-
-    signals.push(dividers[0].clone());
-    signals.push(dividers[1].clone());
-    signals.sort();
+        .map(|line| line.parse::<Signal>())
+        .collect::<Result<Vec<Signal>, _>>()?;
 
-    Ok(dividers
-        .iter()
-        .map(|divider| 1 + signals.iter().position(|signal| signal == divider).unwrap())
-        .product::<usize>())
+    Ok(locate_dividers(&signals, &dividers).iter().product())
 }
 
 pub fn run() {
@@ -126,6 +458,7 @@ pub fn run() {
 mod tests {
 
     use super::*;
+    use crate::test_utils::XorShift;
 
     #[test]
     fn test_part_one() {
@@ -138,6 +471,132 @@ mod tests {
         let input = &read_file_to_string("./examples/day13.txt");
         assert_eq!(part_two(input).ok(), Some(140));
     }
-}
 
-//6076
+    #[test]
+    fn test_round_trip() {
+        let input = &read_file_to_string("./examples/day13.txt");
+        for line in input.lines().filter(|line| !line.is_empty()) {
+            let signal = line.parse::<Signal>().unwrap();
+            assert_eq!(signal.to_string(), line);
+
+            let json: Value = serde_json::from_str(line).unwrap();
+            assert_eq!(Value::from(&signal), json);
+            assert_eq!(Signal::try_from(&json).unwrap().to_string(), line);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| s.parse::<Signal>().unwrap_err().position;
+        assert_eq!(err("[1,[2,x]]"), 6);
+        assert_eq!(err("[1,2"), 4);
+        assert_eq!(err("[1]]"), 3);
+        assert_eq!(err("[99999999999999999999999]"), 1);
+    }
+
+    fn random_signal(rng: &mut XorShift, depth: u32) -> Signal {
+        if depth == 0 || rng.next(3) == 0 {
+            Signal::Value(rng.next(4))
+        } else {
+            Signal::List(
+                (0..rng.next(4))
+                    .map(|_| random_signal(rng, depth - 1))
+                    .collect(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_total_order() {
+        let mut rng = XorShift(0x2022_1213);
+        let signals = (0..60)
+            .map(|_| random_signal(&mut rng, 4))
+            .collect::<Vec<Signal>>();
+
+        for a in &signals {
+            for b in &signals {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                assert_eq!(a.partial_cmp(b), Some(a.cmp(b)));
+                assert_eq!(a == b, a.cmp(b) == Ordering::Equal);
+                for c in &signals {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{} <= {} <= {}", a, b, c);
+                    }
+                }
+            }
+        }
+
+        let mut sorted = signals.clone();
+        sort_packets(&mut sorted);
+        assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn test_compare_packets() {
+        let cmp = |a: &str, b: &str| compare_packets(a, b).unwrap();
+        assert_eq!(cmp("[1,1,3,1,1]", "[1,1,5,1,1]"), Ordering::Less);
+        assert_eq!(cmp("[[4,4],4,4]", "[[4,4],4,4,4]"), Ordering::Less);
+        assert_eq!(cmp("[9]", "[[8,7,6]]"), Ordering::Greater);
+        assert_eq!(cmp("2", "[[[2]]]"), Ordering::Equal);
+        assert_eq!(cmp("[[],[3]]", "[[[]],[3]]"), Ordering::Less);
+        // Malformed packets are reported even after the order is known.
+        let err = |a: &str, b: &str| compare_packets(a, b).unwrap_err().position;
+        assert_eq!(err("[1,x", "[2]"), 3);
+        assert_eq!(err("[1,x]", "[1,2]"), 3);
+        assert_eq!(err("[1,2", "[1,2]"), 4);
+        assert_eq!(err("]]", "]]"), 0);
+        assert_eq!(err("[[1]", "[[1]"), 4);
+        assert_eq!(err("[1[2]]", "[1[2]]"), 2);
+        assert_eq!(err("[1],", "[2]"), 3);
+        assert_eq!(err("[,1]", "[2]"), 1);
+        assert_eq!(err("[1,]", "[1]"), 3);
+        assert_eq!(err("", "[1]"), 0);
+
+        let mut rng = XorShift(0x1312_2022);
+        let signals = (0..60)
+            .map(|_| random_signal(&mut rng, 4))
+            .collect::<Vec<Signal>>();
+        for a in &signals {
+            for b in &signals {
+                let (left, right) = (a.to_string(), b.to_string());
+                assert_eq!(cmp(&left, &right), a.packet_cmp(b), "{} {}", left, right);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pair_checker() {
+        let orderings = PairChecker::new("[1]\n[2]\n\n\n[3]\n[3]\n\n[[4]]\n[]".as_bytes())
+            .collect::<BoxResult<Vec<Ordering>>>()
+            .unwrap();
+        assert_eq!(
+            orderings,
+            vec![Ordering::Less, Ordering::Equal, Ordering::Greater]
+        );
+        assert!(sum_ordered_pairs("[1]\n[2]\n\n[3]\n".as_bytes()).is_err());
+        assert!(sum_ordered_pairs("]]\n]]\n\n[1[2]\n[2]\n".as_bytes()).is_err());
+        assert_eq!(
+            sum_ordered_pairs_in_file("./examples/day13.txt").ok(),
+            Some(13)
+        );
+    }
+
+    #[test]
+    fn test_locate_dividers() {
+        let packets = ["[1,1]", "[[2]]", "[3]", "[]"].map(|p| p.parse::<Signal>().unwrap());
+        let dividers = ["[[2]]", "[[6]]"].map(|p| p.parse::<Signal>().unwrap());
+        assert_eq!(locate_dividers(&packets, &dividers), vec![4, 6]);
+
+        let packets = ["[[6]]", "2", "[[2]]", "[6]"].map(|p| p.parse::<Signal>().unwrap());
+        assert_eq!(locate_dividers(&packets, &dividers), vec![3, 6]);
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let parse = |s: &str| s.parse::<Signal>().unwrap();
+        assert_ne!(parse("2"), parse("[[2]]"));
+        assert_eq!(parse("2").packet_cmp(&parse("[[2]]")), Ordering::Equal);
+        assert_ne!(parse("2").cmp(&parse("[[2]]")), Ordering::Equal);
+        assert_eq!(parse("[2,1]").cmp(&parse("[[2],0]")), Ordering::Greater);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
-    collections::HashSet,
     ops::{Add, Sub},
+    str::FromStr,
 };
 
 use crate::{read_file_to_string, solve, AOCError, BoxResult};
@@ -14,13 +14,9 @@ struct Point {
 impl Point {
     fn points_between(self, other: Point) -> Vec<Point> {
         let delta = (other - self).signum();
-        let res = (0..self.manhattan_distance(other) + 1)
-            .map(|i| {
-                let p = self + delta.mul(i as isize);
-                p
-            })
-            .collect::<Vec<Point>>();
-        res
+        (0..self.manhattan_distance(other) + 1)
+            .map(|i| self + delta.mul(i as isize))
+            .collect::<Vec<Point>>()
     }
 
     fn manhattan_distance(self, other: Point) -> usize {
@@ -42,16 +38,15 @@ impl Point {
     }
 }
 
-impl From<String> for Point {
-    fn from(input: String) -> Self {
-        if let Some((x, y)) = input.split_once(",") {
-            Point {
-                x: x.parse::<isize>().unwrap(),
-                y: y.parse::<isize>().unwrap(),
-            }
-        } else {
-            panic!("{}", AOCError)
-        }
+impl FromStr for Point {
+    type Err = AOCError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (x, y) = input.trim().split_once(',').ok_or(AOCError)?;
+        Ok(Point {
+            x: x.parse::<isize>().map_err(|_| AOCError)?,
+            y: y.parse::<isize>().map_err(|_| AOCError)?,
+        })
     }
 }
 
@@ -77,110 +72,342 @@ impl Sub for Point {
     }
 }
 
-fn part_one(input: &str) -> BoxResult<usize> {
-    let source_sand: Point = Point { x: 500, y: 0 };
-    let rocks: HashSet<Point> = input
-        .lines()
-        .flat_map(|line| {
-            line.split("->")
-                .zip(line.split("->").skip(1))
-                .map(|(p1, p2)| {
-                    Point::from(p1.trim().to_string())
-                        .points_between(Point::from(p2.trim().to_string()))
-                })
-        })
-        .into_iter()
-        .flatten()
-        .collect::<HashSet<Point>>();
-    let mut sands: HashSet<Point> = HashSet::new();
-    let x_max = 550;
-    let y_max = 200;
-
-    'outer: loop {
-        let mut unit: Point = source_sand.clone();
-        '_inner: loop {
-            if unit.x == x_max || unit.y == y_max {
-                break 'outer;
+fn parse_rocks(input: &str) -> BoxResult<Vec<Point>> {
+    let mut rocks = vec![];
+    for line in input.lines() {
+        let corners = line
+            .split("->")
+            .map(|p| p.parse::<Point>())
+            .collect::<Result<Vec<Point>, _>>()?;
+        corners
+            .windows(2)
+            .for_each(|w| rocks.extend(w[0].points_between(w[1])));
+    }
+    Ok(rocks)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tile {
+    Air,
+    Rock,
+    Sand,
+}
+
+// Sand can move down, down-left or down-right, in that order of preference.
+const MOVES: [Point; 3] = [
+    Point { x: 0, y: 1 },
+    Point { x: -1, y: 1 },
+    Point { x: 1, y: 1 },
+];
+
+#[derive(Clone, Debug)]
+pub struct CaveConfig {
+    pub sources: Vec<(isize, isize)>,
+    // Distance between the lowest rock and the floor, if there is a floor.
+    pub floor_depth: Option<isize>,
+    // Closed sides act as walls right next to the outermost rocks and sources.
+    pub closed_sides: bool,
+}
+
+impl Default for CaveConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![(500, 0)],
+            floor_depth: None,
+            closed_sides: false,
+        }
+    }
+}
+
+impl CaveConfig {
+    pub fn with_floor(self, depth: isize) -> Self {
+        Self {
+            floor_depth: Some(depth),
+            ..self
+        }
+    }
+}
+
+// Dense grid covering every cell a grain can rest on. With open sides and no
+// floor, a grain leaving the grid falls into the abyss. The floor is not
+// stored: with open sides the grid covers the triangle sand can fill under
+// each source, so sand never goes around it.
+pub struct Cave {
+    grid: Vec<Tile>,
+    x_min: isize,
+    width: usize,
+    height: usize,
+    // Lowest rock or source, the floor is measured from it.
+    y_max: isize,
+    floor: Option<isize>,
+    closed_sides: bool,
+    sources: Vec<Point>,
+    // Path followed by the last grain of each source. The next one follows
+    // the same path up to the cell before the one where the last grain came
+    // to rest, or up to a cell since filled by a grain from another source.
+    paths: Vec<Vec<Point>>,
+    next_source: usize,
+}
+
+impl Cave {
+    // The floor is placed below the lowest rock or source, `y_max` when given.
+    // Sand never moves it, but is included in the width of the grid.
+    fn new(rocks: &[Point], sands: &[Point], y_max: Option<isize>, config: &CaveConfig) -> Self {
+        let sources = config
+            .sources
+            .iter()
+            .map(|&(x, y)| Point { x, y })
+            .collect::<Vec<Point>>();
+        let y_max = y_max.unwrap_or_else(|| {
+            rocks
+                .iter()
+                .chain(sources.iter())
+                .map(|p| p.y)
+                .max()
+                .unwrap_or(0)
+        });
+        let floor = config.floor_depth.map(|depth| y_max + depth);
+        let spread = |p: &Point| match floor {
+            Some(f) if !config.closed_sides => f - p.y,
+            _ => 0,
+        };
+        let occupied = || rocks.iter().chain(sands).chain(sources.iter());
+        let x_min = occupied().map(|p| p.x - spread(p)).min().unwrap_or(0);
+        let x_max = occupied().map(|p| p.x + spread(p)).max().unwrap_or(0);
+        let width = (x_max - x_min + 1) as usize;
+        let height = floor.unwrap_or(y_max + 1) as usize;
+
+        let mut cave = Cave {
+            grid: vec![Tile::Air; width * height],
+            x_min,
+            width,
+            height,
+            y_max,
+            floor,
+            closed_sides: config.closed_sides,
+            paths: sources.iter().map(|&source| vec![source]).collect(),
+            sources,
+            next_source: 0,
+        };
+        rocks.iter().for_each(|&rock| cave.set(rock, Tile::Rock));
+        sands.iter().for_each(|&sand| cave.set(sand, Tile::Sand));
+        for i in 0..cave.sources.len() {
+            if cave.get(cave.sources[i]) != Some(Tile::Air) {
+                cave.paths[i].clear();
             }
-            let bottom = unit + (Point { x: 0, y: 1 });
-            let bottom_left = unit + (Point { x: -1, y: 1 });
-            let bottom_right = unit + (Point { x: 1, y: 1 });
-
-            if !rocks.contains(&bottom) && !sands.contains(&bottom) {
-                unit = bottom;
-                continue;
-            } else if !rocks.contains(&bottom_left) && !sands.contains(&bottom_left) {
-                unit = bottom_left;
-                continue;
-            } else if !rocks.contains(&bottom_right) && !sands.contains(&bottom_right) {
-                unit = bottom_right;
-                continue;
-            } else {
-                sands.insert(unit);
-                break;
+        }
+        cave
+    }
+
+    pub fn from_scan(input: &str, config: &CaveConfig) -> BoxResult<Self> {
+        Ok(Cave::new(&parse_rocks(input)?, &[], None, config))
+    }
+
+    // Loads a cave saved with `to_picture` or drawn by `render`. The optional
+    // first line holds the coordinates of the top-left cell, `0,0` otherwise,
+    // and the y of the lowest rock. The sources are read from the `+` of the
+    // picture rather than from the configuration.
+    pub fn from_picture(picture: &str, config: &CaveConfig) -> BoxResult<Self> {
+        let mut lines = picture.lines().peekable();
+        let (origin, y_max) = match lines.next_if(|line| line.contains(',')) {
+            Some(header) => match header.split_once(' ') {
+                Some((origin, y_max)) => (origin.parse()?, Some(y_max.trim().parse::<isize>()?)),
+                None => (header.parse()?, None),
+            },
+            None => (Point { x: 0, y: 0 }, None),
+        };
+        let (mut rocks, mut sands, mut sources) = (vec![], vec![], vec![]);
+        for (y, line) in lines.enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let p = origin
+                    + Point {
+                        x: x as isize,
+                        y: y as isize,
+                    };
+                match c {
+                    '#' => rocks.push(p),
+                    'o' => sands.push(p),
+                    '+' => sources.push((p.x, p.y)),
+                    '.' => {}
+                    _ => return Err(Box::new(AOCError)),
+                }
             }
         }
+
+        let config = CaveConfig {
+            sources,
+            ..config.clone()
+        };
+        Ok(Cave::new(&rocks, &sands, y_max, &config))
     }
-    Ok(sands.len())
-}
 
-fn part_two(input: &str) -> BoxResult<usize> {
-    let source_sand: Point = Point { x: 500, y: 0 };
-    let rocks: HashSet<Point> = input
-        .lines()
-        .flat_map(|line| {
-            line.split("->")
-                .zip(line.split("->").skip(1))
-                .map(|(p1, p2)| {
-                    Point::from(p1.trim().to_string())
-                        .points_between(Point::from(p2.trim().to_string()))
-                })
-        })
-        .into_iter()
-        .flatten()
-        .collect::<HashSet<Point>>();
-    let mut sands: HashSet<Point> = HashSet::new();
-    let (x_max, y_max) = rocks.iter().fold((0isize, 0isize), |(x_max, y_max), &val| {
-        (val.x.max(x_max), val.y.max(y_max))
-    });
-    let floor: HashSet<Point> =
-        HashSet::from_iter((Point { x: 0, y: y_max + 2 }).points_between(Point {
-            x: x_max * 3,
-            y: y_max + 2,
-        }));
-
-    'outer: loop {
-        let mut unit: Point = source_sand.clone();
-        '_inner: loop {
-            if sands.contains(&source_sand) {
-                break 'outer;
+    fn index(&self, p: Point) -> Option<usize> {
+        let x = p.x - self.x_min;
+        if x < 0 || p.y < 0 || x as usize >= self.width || p.y as usize >= self.height {
+            return None;
+        }
+        Some(p.y as usize * self.width + x as usize)
+    }
+
+    fn get(&self, p: Point) -> Option<Tile> {
+        if Some(p.y) == self.floor {
+            return Some(Tile::Rock);
+        }
+        match self.index(p) {
+            Some(i) => Some(self.grid[i]),
+            None if self.closed_sides && p.y >= 0 && (p.y as usize) < self.height => {
+                Some(Tile::Rock)
+            }
+            None => None,
+        }
+    }
+
+    fn set(&mut self, p: Point, tile: Tile) {
+        if let Some(i) = self.index(p) {
+            self.grid[i] = tile;
+        }
+    }
+
+    // Drops a grain from the next source that is not blocked and returns
+    // where it came to rest, or `None` if it fell into the abyss or every
+    // source is blocked.
+    pub fn drop_grain(&mut self) -> Option<(isize, isize)> {
+        for _ in 0..self.sources.len() {
+            let i = self.next_source;
+            self.next_source = (i + 1) % self.sources.len();
+
+            let mut path = std::mem::take(&mut self.paths[i]);
+            if let Some(filled) = path.iter().position(|&p| self.get(p) != Some(Tile::Air)) {
+                path.truncate(filled);
+            }
+            let res = self.fall(&mut path);
+            self.paths[i] = path;
+            match res {
+                Some(Some(p)) => return Some((p.x, p.y)),
+                Some(None) => return None,
+                None => continue,
+            }
+        }
+        None
+    }
+
+    // Follows the path until the grain rests (`Some(Some(_))`), falls into the
+    // abyss (`Some(None)`) or the path is empty because its source is blocked.
+    fn fall(&mut self, path: &mut Vec<Point>) -> Option<Option<Point>> {
+        while let Some(&unit) = path.last() {
+            let mut next = None;
+            for m in MOVES {
+                match self.get(unit + m) {
+                    Some(Tile::Air) => {
+                        next = Some(unit + m);
+                        break;
+                    }
+                    None => return Some(None),
+                    _ => {}
+                }
             }
-            let bottom = unit + (Point { x: 0, y: 1 });
-            let bottom_left = unit + (Point { x: -1, y: 1 });
-            let bottom_right = unit + (Point { x: 1, y: 1 });
-
-            if !rocks.contains(&bottom) && !sands.contains(&bottom) && !floor.contains(&bottom) {
-                unit = bottom;
-                continue;
-            } else if !rocks.contains(&bottom_left)
-                && !sands.contains(&bottom_left)
-                && !floor.contains(&bottom_left)
-            {
-                unit = bottom_left;
-                continue;
-            } else if !rocks.contains(&bottom_right)
-                && !sands.contains(&bottom_right)
-                && !floor.contains(&bottom_right)
-            {
-                unit = bottom_right;
-                continue;
-            } else {
-                sands.insert(unit);
-                break;
+            match next {
+                Some(p) => path.push(p),
+                None => {
+                    self.set(unit, Tile::Sand);
+                    path.pop();
+                    return Some(Some(unit));
+                }
             }
         }
+        None
+    }
+
+    pub fn fill(&mut self) -> usize {
+        std::iter::from_fn(|| self.drop_grain()).count()
+    }
+
+    // Top-left corner and bottom-right corner of the rocks, sand and sources.
+    fn occupied_bounds(&self) -> (Point, Point) {
+        let occupied = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.grid[y * self.width + x] != Tile::Air)
+            .map(|(x, y)| Point {
+                x: x as isize + self.x_min,
+                y: y as isize,
+            })
+            .chain(self.sources.iter().copied())
+            .collect::<Vec<Point>>();
+        (
+            Point {
+                x: occupied.iter().map(|p| p.x).min().unwrap_or(0),
+                y: occupied.iter().map(|p| p.y).min().unwrap_or(0),
+            },
+            Point {
+                x: occupied.iter().map(|p| p.x).max().unwrap_or(0),
+                y: occupied.iter().map(|p| p.y).max().unwrap_or(0),
+            },
+        )
     }
-    Ok(sands.len())
+
+    fn draw(&self, min: Point, max: Point) -> String {
+        (min.y..=max.y)
+            .map(|y| {
+                (min.x..=max.x)
+                    .map(|x| match self.get(Point { x, y }) {
+                        _ if self.sources.contains(&Point { x, y }) => '+',
+                        Some(Tile::Rock) => '#',
+                        Some(Tile::Sand) => 'o',
+                        _ => '.',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Draws the cave as in the puzzle text, cropped to the rocks, the sand and
+    // the sources, plus the floor if there is one.
+    pub fn render(&self) -> String {
+        let (min, max) = self.occupied_bounds();
+        let max = Point {
+            y: max.y.max(self.floor.unwrap_or(max.y)),
+            ..max
+        };
+        self.draw(min, max)
+    }
+
+    // Same picture as `render` without the floor, preceded by the coordinates
+    // of its top-left cell and the y of the lowest rock so that `from_picture`
+    // can load it back with the floor at the same place.
+    pub fn to_picture(&self) -> String {
+        let (min, max) = self.occupied_bounds();
+        format!(
+            "{},{} {}\n{}",
+            min.x,
+            min.y,
+            self.y_max,
+            self.draw(min, max)
+        )
+    }
+}
+
+pub fn render_cave(input: &str, config: &CaveConfig) -> BoxResult<String> {
+    let mut cave = Cave::from_scan(input, config)?;
+    cave.fill();
+    Ok(cave.render())
+}
+
+// Yields the rendered cave each time a grain comes to rest.
+pub fn animate(input: &str, config: &CaveConfig) -> BoxResult<impl Iterator<Item = String>> {
+    let mut cave = Cave::from_scan(input, config)?;
+    Ok(std::iter::from_fn(move || {
+        cave.drop_grain()?;
+        Some(cave.render())
+    }))
+}
+
+fn part_one(input: &str) -> BoxResult<usize> {
+    Ok(Cave::from_scan(input, &CaveConfig::default())?.fill())
+}
+
+fn part_two(input: &str) -> BoxResult<usize> {
+    Ok(Cave::from_scan(input, &CaveConfig::default().with_floor(2))?.fill())
 }
 
 pub fn run() {
@@ -205,6 +432,124 @@ mod tests {
         let input = &read_file_to_string("./examples/day14.txt");
         assert_eq!(part_two(input).ok(), Some(93));
     }
-}
 
-//6076
+    #[test]
+    fn test_render_cave() {
+        let input = &read_file_to_string("./examples/day14.txt");
+        assert_eq!(
+            render_cave(input, &CaveConfig::default()).ok(),
+            Some(
+                [
+                    "......+...",
+                    "..........",
+                    "......o...",
+                    ".....ooo..",
+                    "....#ooo##",
+                    "...o#ooo#.",
+                    "..###ooo#.",
+                    "....oooo#.",
+                    ".o.ooooo#.",
+                    "#########.",
+                ]
+                .join("\n")
+            )
+        );
+    }
+
+    #[test]
+    fn test_animate() {
+        let input = &read_file_to_string("./examples/day14.txt");
+        let frames = animate(input, &CaveConfig::default())
+            .unwrap()
+            .collect::<Vec<String>>();
+        assert_eq!(frames.len(), 24);
+        assert_eq!(
+            frames[1],
+            [
+                "......+...",
+                "..........",
+                "..........",
+                "..........",
+                "....#...##",
+                "....#...#.",
+                "..###...#.",
+                "........#.",
+                ".....oo.#.",
+                "#########.",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            animate(input, &CaveConfig::default().with_floor(2))
+                .unwrap()
+                .count(),
+            93
+        );
+    }
+
+    #[test]
+    fn test_picture_round_trip() {
+        let input = &read_file_to_string("./examples/day14.txt");
+        let mut cave = Cave::from_scan(input, &CaveConfig::default()).unwrap();
+        for _ in 0..5 {
+            cave.drop_grain();
+        }
+        let picture = cave.to_picture();
+        assert!(picture.starts_with("494,0 9\n......+...\n"));
+
+        let mut loaded = Cave::from_picture(&picture, &CaveConfig::default()).unwrap();
+        assert_eq!(loaded.to_picture(), picture);
+        assert_eq!(loaded.fill(), 24 - 5);
+        assert_eq!(cave.fill(), 24 - 5);
+        assert_eq!(loaded.render(), cave.render());
+
+        // Sand piled on the rocks must not move the floor down.
+        let config = CaveConfig::default().with_floor(2);
+        let mut cave = Cave::from_scan(input, &config).unwrap();
+        for _ in 0..40 {
+            cave.drop_grain();
+        }
+        let mut loaded = Cave::from_picture(&cave.to_picture(), &config).unwrap();
+        assert_eq!(loaded.to_picture(), cave.to_picture());
+        assert_eq!(loaded.fill(), 93 - 40);
+        assert_eq!(cave.fill(), 93 - 40);
+        assert_eq!(loaded.render(), cave.render());
+    }
+
+    #[test]
+    fn test_load_render() {
+        let input = &read_file_to_string("./examples/day14.txt");
+        let frames = animate(input, &CaveConfig::default())
+            .unwrap()
+            .collect::<Vec<String>>();
+        let mut loaded = Cave::from_picture(&frames[1], &CaveConfig::default()).unwrap();
+        assert_eq!(loaded.render(), frames[1]);
+        assert_eq!(loaded.fill(), 24 - 2);
+        assert_eq!(loaded.render(), frames[23]);
+        assert!(Cave::from_picture("..+x", &CaveConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_sources_and_sides() {
+        let picture = ["0,0", "+...+", ".....", "#####"].join("\n");
+        let mut cave = Cave::from_picture(&picture, &CaveConfig::default()).unwrap();
+        assert_eq!(cave.drop_grain(), None);
+
+        let closed = CaveConfig {
+            closed_sides: true,
+            ..CaveConfig::default()
+        };
+        let mut cave = Cave::from_picture(&picture, &closed).unwrap();
+        assert_eq!(cave.drop_grain(), Some((0, 1)));
+        assert_eq!(cave.drop_grain(), Some((4, 1)));
+        assert_eq!(cave.fill(), 4);
+        assert_eq!(cave.render(), ["+...+", "oo.oo", "#####"].join("\n"));
+        let mut cave =
+            Cave::from_picture(&["+...+", ".....", "#####"].join("\n"), &closed).unwrap();
+        assert_eq!(cave.fill(), 2 + 4);
+
+        let mut cave =
+            Cave::from_scan("500,3 -> 500,3", &CaveConfig::default().with_floor(3)).unwrap();
+        assert_eq!(cave.fill(), 35);
+    }
+}
@@ -1,8 +1,9 @@
-use std::cmp;
+use std::error::Error;
+use std::{cmp, fmt};
 
 use regex::Regex;
 
-use crate::{read_file_to_string, AOCError, BoxResult};
+use crate::{pgm, read_file_to_string, solve, BoxResult};
 
 #[derive(Clone, Copy, Debug)]
 struct Sensor {
@@ -10,44 +11,283 @@ struct Sensor {
     closest_beacon: Point,
 }
 
-struct Zone {
+pub struct Zone {
     min: isize,
     max: isize,
     sensors: Vec<Sensor>,
 }
 
 impl Zone {
-    fn coverage_on_y(&self, y: isize) -> Ranges {
+    pub fn new(input: &str) -> Self {
+        Zone {
+            min: isize::MIN,
+            max: isize::MAX,
+            sensors: parse_sensors(input),
+        }
+    }
+
+    // Restricts the zone to `min..=max` on both axes.
+    pub fn with_limits(self, min: isize, max: isize) -> Self {
+        Zone { min, max, ..self }
+    }
+
+    // Intervals covered by at least one sensor on the line where the `fixed`
+    // coordinate is `value`, beacons included, sorted and merged.
+    fn coverage_on_line(
+        &self,
+        value: isize,
+        fixed: fn(Point) -> isize,
+        free: fn(Point) -> isize,
+    ) -> Vec<(isize, isize)> {
         let mut ranges = Ranges { ranges: vec![] };
         for s in &self.sensors {
             let distance = s.location.manhattan_distance(s.closest_beacon) as isize;
-            let offset = s.location.y.abs_diff(y) as isize;
-            let remaining: isize = distance - offset;
-
-            if remaining <= 0 {
+            let remaining = distance - fixed(s.location).abs_diff(value) as isize;
+            if remaining < 0 {
                 continue;
             }
+            let range = Range {
+                start: cmp::max(free(s.location) - remaining, self.min),
+                end: cmp::min(free(s.location) + remaining, self.max),
+            };
+            if range.start <= range.end {
+                ranges.union(range);
+            }
+        }
+
+        let mut res = ranges
+            .ranges
+            .iter()
+            .map(|r| (r.start, r.end))
+            .collect::<Vec<(isize, isize)>>();
+        res.sort();
+        res
+    }
 
-            let mut min_x = cmp::max(s.location.x - remaining, self.min);
-            let mut max_x = cmp::min(s.location.x + remaining, self.max);
+    pub fn row_coverage(&self, y: isize) -> Vec<(isize, isize)> {
+        self.coverage_on_line(y, |p| p.y, |p| p.x)
+    }
+
+    pub fn column_coverage(&self, x: isize) -> Vec<(isize, isize)> {
+        self.coverage_on_line(x, |p| p.x, |p| p.y)
+    }
 
-            if s.closest_beacon.y == y && s.closest_beacon.x == min_x {
-                min_x += 1;
+    pub fn sensors_covering(&self, x: isize, y: isize) -> usize {
+        let p = Point { x, y };
+        self.sensors
+            .iter()
+            .filter(|s| {
+                s.location.manhattan_distance(p) <= s.location.manhattan_distance(s.closest_beacon)
+            })
+            .count()
+    }
+
+    // Number of sensors covering the centre of each cell when the box between
+    // `min` and `max` is scaled down to `width` x `height` cells. A gap smaller
+    // than a cell can be missed.
+    pub fn coverage_grid(
+        &self,
+        min: (isize, isize),
+        max: (isize, isize),
+        width: usize,
+        height: usize,
+    ) -> Vec<Vec<usize>> {
+        let center = |from: isize, to: isize, i: usize, n: usize| {
+            from + ((2 * i as isize + 1) * (to - from + 1)) / (2 * n as isize)
+        };
+        (0..height)
+            .map(|j| {
+                let y = center(min.1, max.1, j, height);
+                (0..width)
+                    .map(|i| self.sensors_covering(center(min.0, max.0, i, width), y))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // `.` for uncovered cells, the number of covering sensors otherwise (`+`
+    // above nine).
+    pub fn coverage_ascii(&self, grid: &[Vec<usize>]) -> String {
+        grid.iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&n| match n {
+                        0 => '.',
+                        n if n < 10 => char::from_digit(n as u32, 10).unwrap(),
+                        _ => '+',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Plain (P2) grayscale image, uncovered cells are black.
+    pub fn coverage_pgm(&self, grid: &[Vec<usize>]) -> String {
+        pgm(grid, self.sensors.len())
+    }
+
+    fn is_covered(&self, p: Point) -> bool {
+        self.sensors.iter().any(|s| {
+            s.location.manhattan_distance(p) <= s.location.manhattan_distance(s.closest_beacon)
+        })
+    }
+
+    // Uncovered cells of the diagonal line `x + y = a` (`sum`) or `x - y = a`
+    // inside the zone, at most `limit` of them. Along the line, with `x = t`,
+    // the cells covered by a sensor form an interval of `t` since
+    // `|p| + |q| = max(|p + q|, |p - q|)`.
+    fn uncovered_on_diagonal(&self, sum: bool, a: isize, limit: usize) -> Vec<Point> {
+        let (lo, hi) = if sum {
+            (
+                cmp::max(self.min, a - self.max),
+                cmp::min(self.max, a - self.min),
+            )
+        } else {
+            (
+                cmp::max(self.min, a + self.min),
+                cmp::min(self.max, a + self.max),
+            )
+        };
+        let mut covered = self
+            .sensors
+            .iter()
+            .filter_map(|s| {
+                let r = s.location.manhattan_distance(s.closest_beacon) as isize;
+                let (sx, sy) = (s.location.x, s.location.y);
+                let (fixed, c) = if sum {
+                    (a - sx - sy, a + sx - sy)
+                } else {
+                    (a - sx + sy, a + sx + sy)
+                };
+                if fixed.abs() > r {
+                    return None;
+                }
+                Some((-((r - c).div_euclid(2)), (c + r).div_euclid(2)))
+            })
+            .collect::<Vec<(isize, isize)>>();
+        covered.sort();
+
+        let mut res = vec![];
+        let mut t = lo;
+        let mut gaps = covered
+            .iter()
+            .map(|&(start, end)| (start, end.saturating_add(1)))
+            .chain([(hi.saturating_add(1), hi.saturating_add(1))]);
+        while t <= hi && res.len() < limit {
+            let (start, next) = gaps.next().unwrap();
+            while t < start.min(hi + 1) && res.len() < limit {
+                let y = if sum { a - t } else { t - a };
+                res.push(Point { x: t, y });
+                t += 1;
             }
-            if s.closest_beacon.y == y && s.closest_beacon.x == max_x {
-                max_x -= 1;
+            t = t.max(next);
+        }
+        res
+    }
+
+    // An uncovered cell next to a covered one is just outside the radius of
+    // the sensor covering it, so on one of the lines bounding its diamond. Any
+    // group of uncovered cells has such a cell unless it fills the zone, so
+    // those lines and two corners of the zone are enough to tell whether there
+    // is no uncovered cell, a single one, or more.
+    fn uncovered_on_boundaries(&self, limit: usize) -> Vec<Point> {
+        let corners = [
+            Point {
+                x: self.min,
+                y: self.min,
+            },
+            Point {
+                x: self.max,
+                y: self.max,
+            },
+        ];
+        let mut points = corners
+            .into_iter()
+            .filter(|&p| !self.is_covered(p))
+            .collect::<Vec<Point>>();
+        points.dedup();
+        for s in &self.sensors {
+            let r = s.location.manhattan_distance(s.closest_beacon) as isize + 1;
+            let (sum, diff) = (s.location.x + s.location.y, s.location.x - s.location.y);
+            for (is_sum, a) in [
+                (true, sum - r),
+                (true, sum + r),
+                (false, diff - r),
+                (false, diff + r),
+            ] {
+                points.extend(self.uncovered_on_diagonal(is_sum, a, limit));
+                points.sort_by_key(|p| (p.x, p.y));
+                points.dedup();
+                if points.len() >= limit {
+                    points.truncate(limit);
+                    return points;
+                }
             }
+        }
+        points
+    }
 
-            ranges.union(Range {
-                start: min_x,
-                end: max_x,
-            });
+    // Uncovered cells of the row inside the zone.
+    fn uncovered_on_row(&self, y: isize) -> Vec<Point> {
+        let mut res = vec![];
+        let mut x = self.min;
+        for (start, end) in self.row_coverage(y) {
+            res.extend((x..start).map(|x| Point { x, y }));
+            x = x.max(end.saturating_add(1));
         }
+        if x <= self.max {
+            res.extend((x..=self.max).map(|x| Point { x, y }));
+        }
+        res
+    }
+
+    // Every uncovered cell of the zone, row by row. This walks every row, so
+    // the zone has to be bounded with `with_limits`.
+    pub fn uncovered_points(&self) -> Vec<(isize, isize)> {
+        (self.min..=self.max)
+            .flat_map(|y| self.uncovered_on_row(y))
+            .map(|p| (p.x, p.y))
+            .collect()
+    }
+
+    fn distress_beacon(&self) -> Result<Point, BeaconError> {
+        const LIMIT: usize = 16;
+        match self.uncovered_on_boundaries(LIMIT).as_slice() {
+            [] => Err(BeaconError::NotFound),
+            [p] => Ok(*p),
+            points => Err(BeaconError::Ambiguous(
+                points.iter().map(|p| (p.x, p.y)).collect(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BeaconError {
+    NotFound,
+    // Some of the uncovered points, `Zone::uncovered_points` lists them all.
+    Ambiguous(Vec<(isize, isize)>),
+}
 
-        ranges
+impl fmt::Display for BeaconError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BeaconError::NotFound => write!(f, "no uncovered point in the zone"),
+            BeaconError::Ambiguous(points) => {
+                write!(
+                    f,
+                    "{} uncovered candidate points in the zone: {:?}",
+                    points.len(),
+                    points
+                )
+            }
+        }
     }
 }
 
+impl Error for BeaconError {}
+
 #[derive(Clone, Copy, Debug)]
 struct Range {
     start: isize,
@@ -164,95 +404,259 @@ impl Point {
     }
 }
 
-fn part_one(input: &str, y: isize) -> BoxResult<isize> {
-    let zone = Zone {
-        min: isize::MIN,
-        max: isize::MAX,
-        sensors: input
-            .lines()
-            .map(|line| Sensor::from(line.to_string()))
-            .collect(),
+fn parse_sensors(input: &str) -> Vec<Sensor> {
+    input
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| Sensor::from(line.to_string()))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub row: isize,
+    pub limit: isize,
+}
+
+impl Config {
+    pub const EXAMPLE: Config = Config { row: 10, limit: 20 };
+    pub const REAL: Config = Config {
+        row: 2000000,
+        limit: 4000000,
     };
-    Ok(zone
-        .coverage_on_y(y)
-        .ranges
+
+    // The example only has small coordinates, real inputs are in the millions.
+    pub fn detect(input: &str) -> Self {
+        let far = parse_sensors(input)
+            .iter()
+            .flat_map(|s| [s.location.x, s.location.y])
+            .any(|v| v.abs() > 1000);
+        if far {
+            Config::REAL
+        } else {
+            Config::EXAMPLE
+        }
+    }
+}
+
+// Positions of the row where the distress beacon cannot be: covered by a
+// sensor and not holding a known beacon.
+pub fn covered_on_row(input: &str, y: isize) -> isize {
+    let zone = Zone::new(input);
+    let coverage = zone.row_coverage(y);
+    let mut beacons = zone
+        .sensors
+        .iter()
+        .map(|s| s.closest_beacon)
+        .filter(|b| b.y == y)
+        .filter(|b| {
+            coverage
+                .iter()
+                .any(|&(start, end)| (start..=end).contains(&b.x))
+        })
+        .map(|b| b.x)
+        .collect::<Vec<isize>>();
+    beacons.sort();
+    beacons.dedup();
+    coverage
         .iter()
-        .fold(0, |acc, range| acc + 1 + (range.end - range.start)))
+        .map(|(start, end)| end - start + 1)
+        .sum::<isize>()
+        - beacons.len() as isize
 }
 
-fn part_two(input: &str, limit: isize) -> BoxResult<isize> {
-    let limit: isize = limit;
+pub fn tuning_frequency(input: &str, limit: isize) -> BoxResult<isize> {
     let zone: Zone = Zone {
         min: 0,
         max: limit,
-        sensors: input
-            .lines()
-            .map(|line| Sensor::from(line.to_string()))
-            .collect(),
+        sensors: parse_sensors(input),
     };
-    let mut p = None;
-    for y in 0..=limit {
-        let ranges = zone.coverage_on_y(y);
-
-        if ranges.ranges.len() > 1 {
-            let x = ranges
-                .ranges
-                .into_iter()
-                .find(|r| r.start > 0)
-                .unwrap()
-                .start
-                - 1;
-
-            // Check if the candidate is exactly equal to a beacon
-            let new_p = Point { x, y };
-            if zone.sensors.iter().any(|s| new_p == s.closest_beacon) {
-                continue;
-            }
+    let Point { x, y } = zone.distress_beacon()?;
+    Ok(x * 4000000 + y)
+}
 
-            // If not, we found the solution
-            p = Some(new_p);
-            break;
-        }
-    }
+// Answers to both parts for the given row and search limit.
+pub fn solve_with(input: &str, config: &Config) -> BoxResult<(isize, isize)> {
+    Ok((
+        covered_on_row(input, config.row),
+        tuning_frequency(input, config.limit)?,
+    ))
+}
 
-    match p {
-        Some(Point { x, y }) => Ok(x * 4000000 + y),
-        None => panic!("{}", AOCError),
-    }
+fn part_one(input: &str) -> BoxResult<isize> {
+    Ok(covered_on_row(input, Config::detect(input).row))
+}
+
+fn part_two(input: &str) -> BoxResult<isize> {
+    tuning_frequency(input, Config::detect(input).limit)
 }
 
 pub fn run() {
-    let input = &read_file_to_string("./examples/day15.txt");
-    part_one(input, 2000000).ok();
-    part_two(input, 4000000).ok();
+    let input = &read_file_to_string("./inputs/day15.txt");
+    solve!(1, part_one, input);
+    solve!(2, part_two, input);
+}
+
+pub fn run_rows(rows: &[isize]) {
+    let input = &read_file_to_string("./inputs/day15.txt");
+    for &row in rows {
+        println!("row {}: {}", row, covered_on_row(input, row));
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::test_utils::XorShift;
 
     #[test]
     fn test_part_one_example() {
         let input = &read_file_to_string("./examples/day15.txt");
-        assert_eq!(part_one(input, 10).ok(), Some(26));
+        assert_eq!(part_one(input).ok(), Some(26));
+        assert_eq!(covered_on_row(input, 10), 26);
     }
 
     #[test]
     fn test_part_two_example() {
         let input = &read_file_to_string("./examples/day15.txt");
-        assert_eq!(part_two(input, 20).ok(), Some(56000011));
+        assert_eq!(part_two(input).ok(), Some(56000011));
+        assert_eq!(tuning_frequency(input, 20).ok(), Some(56000011));
+        assert_eq!(
+            solve_with(input, &Config::EXAMPLE).ok(),
+            Some((26, 56000011))
+        );
+    }
+
+    fn brute_force_covered(sensors: &[Sensor], y: isize) -> isize {
+        (-100..=100)
+            .map(|x| Point { x, y })
+            .filter(|&p| sensors.iter().all(|s| s.closest_beacon != p))
+            .filter(|&p| {
+                sensors.iter().any(|s| {
+                    s.location.manhattan_distance(p)
+                        <= s.location.manhattan_distance(s.closest_beacon)
+                })
+            })
+            .count() as isize
+    }
+
+    #[test]
+    fn test_covered_on_row() {
+        let input = &read_file_to_string("./examples/day15.txt");
+        let sensors = parse_sensors(input);
+        for y in -20..=40 {
+            assert_eq!(
+                covered_on_row(input, y),
+                brute_force_covered(&sensors, y),
+                "row {}",
+                y
+            );
+        }
+
+        let input = "Sensor at x=0, y=0: closest beacon is at x=1, y=0";
+        assert_eq!(covered_on_row(input, 0), 2);
+        assert_eq!(covered_on_row(input, 1), 1);
+        assert_eq!(covered_on_row(input, 2), 0);
     }
 
     #[test]
     fn test_part_one() {
         let input = &read_file_to_string("./inputs/day15.txt");
-        assert_eq!(part_one(input, 2000000).ok(), Some(5525990));
+        assert_eq!(part_one(input).ok(), Some(5525990));
     }
 
     #[test]
     fn test_part_two() {
         let input = &read_file_to_string("./inputs/day15.txt");
-        assert_eq!(part_two(input, 4000000).ok(), Some(11756174628223));
+        assert_eq!(part_two(input).ok(), Some(11756174628223));
+    }
+
+    #[test]
+    fn test_ambiguous_beacon() {
+        let zone = Zone {
+            min: 0,
+            max: 4,
+            sensors: vec![Sensor::from(
+                "Sensor at x=2, y=2: closest beacon is at x=2, y=3".to_string(),
+            )],
+        };
+        assert!(matches!(
+            zone.distress_beacon(),
+            Err(BeaconError::Ambiguous(points)) if points.contains(&(0, 0)) && points.len() > 1
+        ));
+    }
+
+    #[test]
+    fn test_against_brute_force() {
+        let sensor = |x, y, bx, by| Sensor {
+            location: Point { x, y },
+            closest_beacon: Point { x: bx, y: by },
+        };
+        let zone = Zone {
+            min: 0,
+            max: 4,
+            sensors: vec![sensor(1, 0, 0, -1), sensor(1, 5, 2, 2), sensor(4, -1, 6, 0)],
+        };
+        assert_eq!(zone.uncovered_points(), vec![(3, 2), (4, 3)]);
+        assert!(matches!(
+            zone.distress_beacon(),
+            Err(BeaconError::Ambiguous(points)) if points == vec![(3, 2), (4, 3)]
+        ));
+
+        let mut rng = XorShift(0x2022_1215);
+        for _ in 0..3000 {
+            let max = 2 + rng.next(6) as isize;
+            let count = 1 + rng.next(4);
+            let mut coordinate = || rng.next(max as usize + 5) as isize - 2;
+            let sensors = (0..count)
+                .map(|_| {
+                    let (x, y) = (coordinate(), coordinate());
+                    sensor(x, y, coordinate(), coordinate())
+                })
+                .collect::<Vec<Sensor>>();
+            let zone = Zone {
+                min: 0,
+                max,
+                sensors,
+            };
+            let expected = (0..=max)
+                .flat_map(|y| (0..=max).map(move |x| Point { x, y }))
+                .filter(|&p| !zone.is_covered(p))
+                .collect::<Vec<Point>>();
+            let mut points = zone.uncovered_points();
+            points.sort();
+            let mut expected_points = expected.iter().map(|p| (p.x, p.y)).collect::<Vec<_>>();
+            expected_points.sort();
+            assert_eq!(points, expected_points);
+            match (expected.as_slice(), zone.distress_beacon()) {
+                ([], Err(BeaconError::NotFound)) => {}
+                ([p], Ok(beacon)) => assert_eq!(*p, beacon),
+                (_, Err(BeaconError::Ambiguous(found))) if expected.len() > 1 => {
+                    assert!(found.iter().all(|p| expected_points.contains(p)));
+                }
+                (_, res) => panic!("{:?} for {:?}", res, expected_points),
+            }
+        }
+    }
+
+    #[test]
+    fn test_coverage_queries() {
+        let input = &read_file_to_string("./examples/day15.txt");
+        let zone = Zone::new(input);
+        assert_eq!(zone.row_coverage(10), vec![(-2, 24)]);
+        assert_eq!(zone.row_coverage(11), vec![(-3, 13), (15, 25)]);
+        assert_eq!(zone.column_coverage(14), vec![(0, 10), (12, 23)]);
+        assert_eq!(zone.sensors_covering(14, 11), 0);
+        assert_eq!(zone.sensors_covering(2, 10), 3);
+
+        let grid = zone.coverage_grid((0, 0), (20, 20), 21, 21);
+        assert_eq!(grid[11][14], 0);
+        assert_eq!(grid.iter().flatten().filter(|&&n| n == 0).count(), 1);
+        let ascii = zone.coverage_ascii(&grid);
+        assert_eq!(ascii.lines().nth(11).unwrap().chars().nth(14), Some('.'));
+        let pgm = zone.coverage_pgm(&grid);
+        assert!(pgm.starts_with("P2\n21 21\n255\n"));
+        assert!(pgm.lines().all(|line| line.len() <= 70));
     }
 }
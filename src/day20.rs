@@ -1,37 +1,262 @@
-use crate::{read_file_to_string, solve, BoxResult};
+use crate::{read_file_to_string, solve, AOCError, BoxResult};
 
-type Indexed = (usize, isize);
+const NIL: usize = usize::MAX;
 
-fn decode(input: &str, key: isize, rounds: usize) -> isize {
-    let mut data: Vec<Indexed> = input
+#[derive(Clone, Debug)]
+struct Node {
+    value: isize,
+    priority: u64,
+    size: usize,
+    left: usize,
+    right: usize,
+    parent: usize,
+}
+
+// Implicit treap: the nodes are ordered by their position in the list, which
+// is never stored but derived from the subtree sizes. Nodes are kept in their
+// original order in `nodes`, and the parent links give the current position
+// of any of them in O(log n).
+pub struct CircularMixer {
+    nodes: Vec<Node>,
+    root: usize,
+}
+
+impl CircularMixer {
+    pub fn new(values: &[isize]) -> Self {
+        let mut seed: u64 = 0x2022_1220;
+        let mut mixer = CircularMixer {
+            nodes: values
+                .iter()
+                .map(|&value| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    Node {
+                        value,
+                        priority: seed,
+                        size: 1,
+                        left: NIL,
+                        right: NIL,
+                        parent: NIL,
+                    }
+                })
+                .collect(),
+            root: NIL,
+        };
+        for i in 0..values.len() {
+            mixer.root = mixer.merge(mixer.root, i);
+        }
+        mixer
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn size(&self, node: usize) -> usize {
+        if node == NIL {
+            0
+        } else {
+            self.nodes[node].size
+        }
+    }
+
+    fn update(&mut self, node: usize) {
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        self.nodes[node].size = 1 + self.size(left) + self.size(right);
+        for child in [left, right] {
+            if child != NIL {
+                self.nodes[child].parent = node;
+            }
+        }
+    }
+
+    fn merge(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL || b == NIL {
+            let root = if a == NIL { b } else { a };
+            if root != NIL {
+                self.nodes[root].parent = NIL;
+            }
+            return root;
+        }
+        if self.nodes[a].priority > self.nodes[b].priority {
+            let right = self.nodes[a].right;
+            self.nodes[a].right = self.merge(right, b);
+            self.update(a);
+            self.nodes[a].parent = NIL;
+            a
+        } else {
+            let left = self.nodes[b].left;
+            self.nodes[b].left = self.merge(a, left);
+            self.update(b);
+            self.nodes[b].parent = NIL;
+            b
+        }
+    }
+
+    // Splits the subtree into its first `k` nodes and the rest.
+    fn split(&mut self, node: usize, k: usize) -> (usize, usize) {
+        if node == NIL {
+            return (NIL, NIL);
+        }
+        let left = self.nodes[node].left;
+        let res = if self.size(left) >= k {
+            let (a, b) = self.split(left, k);
+            self.nodes[node].left = b;
+            self.update(node);
+            (a, node)
+        } else {
+            let right = self.nodes[node].right;
+            let (a, b) = self.split(right, k - self.size(left) - 1);
+            self.nodes[node].right = a;
+            self.update(node);
+            (node, b)
+        };
+        for root in [res.0, res.1] {
+            if root != NIL {
+                self.nodes[root].parent = NIL;
+            }
+        }
+        res
+    }
+
+    // Current position of the number originally at `index`.
+    pub fn position_of(&self, index: usize) -> usize {
+        let mut node = index;
+        let mut position = self.size(self.nodes[node].left);
+        while self.nodes[node].parent != NIL {
+            let parent = self.nodes[node].parent;
+            if self.nodes[parent].right == node {
+                position += self.size(self.nodes[parent].left) + 1;
+            }
+            node = parent;
+        }
+        position
+    }
+
+    // Number at the given position, wrapping around the list. `None` when the
+    // list is empty.
+    pub fn get(&self, position: usize) -> Option<isize> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut k = position % self.len();
+        let mut node = self.root;
+        loop {
+            let left = self.size(self.nodes[node].left);
+            if k < left {
+                node = self.nodes[node].left;
+            } else if k == left {
+                return Some(self.nodes[node].value);
+            } else {
+                k -= left + 1;
+                node = self.nodes[node].right;
+            }
+        }
+    }
+
+    // Moves the number originally at `index` by `shift` positions in the list
+    // without it.
+    fn move_by(&mut self, index: usize, shift: isize) {
+        if self.len() < 2 {
+            return;
+        }
+        let position = self.position_of(index);
+        let (a, rest) = self.split(self.root, position);
+        let (node, c) = self.split(rest, 1);
+        let remaining = self.merge(a, c);
+
+        let new_position = (position as isize + shift).rem_euclid(self.len() as isize - 1);
+        let (a, c) = self.split(remaining, new_position as usize);
+        let a = self.merge(a, node);
+        self.root = self.merge(a, c);
+    }
+
+    // Moves the number originally at `index` forward by its value.
+    pub fn mix_one(&mut self, index: usize) {
+        self.move_by(index, self.nodes[index].value);
+    }
+
+    pub fn mix(&mut self) {
+        for index in 0..self.len() {
+            self.mix_one(index);
+        }
+    }
+
+    // Mixes `rounds` times, yielding the sequence after each round.
+    pub fn mix_rounds(&mut self, rounds: usize) -> impl Iterator<Item = Vec<isize>> + '_ {
+        (0..rounds).map(|_| {
+            self.mix();
+            self.to_vec()
+        })
+    }
+
+    // Moves the number originally at `index` back by its value, undoing
+    // `mix_one`. The circular order is restored, but the list may end up
+    // rotated compared to the one before mixing.
+    pub fn unmix_one(&mut self, index: usize) {
+        self.move_by(index, -self.nodes[index].value);
+    }
+
+    pub fn unmix(&mut self) {
+        for index in (0..self.len()).rev() {
+            self.unmix_one(index);
+        }
+    }
+
+    // Numbers found `offsets` positions after the `0`, if there is one.
+    pub fn grove_coordinates(&self, offsets: &[usize]) -> Option<Vec<isize>> {
+        let zero = self.nodes.iter().position(|n| n.value == 0)?;
+        let zero_position = self.position_of(zero);
+        offsets
+            .iter()
+            .map(|offset| self.get(zero_position + offset))
+            .collect()
+    }
+
+    pub fn to_vec(&self) -> Vec<isize> {
+        let mut res = Vec::with_capacity(self.len());
+        let mut stack = vec![];
+        let mut node = self.root;
+        while node != NIL || !stack.is_empty() {
+            while node != NIL {
+                stack.push(node);
+                node = self.nodes[node].left;
+            }
+            node = stack.pop().unwrap();
+            res.push(self.nodes[node].value);
+            node = self.nodes[node].right;
+        }
+        res
+    }
+}
+
+pub fn parse_numbers(input: &str, key: isize) -> BoxResult<Vec<isize>> {
+    Ok(input
         .lines()
-        .map(|s| s.parse::<isize>().unwrap() * key)
-        .enumerate()
-        .collect();
+        .map(|s| s.trim().parse::<isize>().map(|v| v * key))
+        .collect::<Result<Vec<isize>, _>>()?)
+}
 
+pub fn decrypt(input: &str, key: isize, rounds: usize, offsets: &[usize]) -> BoxResult<isize> {
+    let mut mixer = CircularMixer::new(&parse_numbers(input, key)?);
     for _ in 0..rounds {
-        for original_index in 0..data.len() {
-            let index = data.iter().position(|x| x.0 == original_index).unwrap();
-            let value = data[index].1;
-            let new_index = index as isize + value;
-            let new_index = new_index.rem_euclid(data.len() as isize - 1);
-            let el = data.remove(index);
-            data.insert(new_index as usize, el);
-        }
+        mixer.mix();
     }
-    let zero_index = data.iter().position(|x| x.1 == 0).unwrap();
-    [1000, 2000, 3000]
-        .iter()
-        .map(|v| data[(zero_index + v) % data.len()].1)
-        .sum()
+    let coordinates = mixer.grove_coordinates(offsets).ok_or(AOCError)?;
+    Ok(coordinates.iter().sum())
 }
 
 fn part_one(input: &str) -> BoxResult<isize> {
-    Ok(decode(input, 1, 1))
+    decrypt(input, 1, 1, &[1000, 2000, 3000])
 }
 
 fn part_two(input: &str) -> BoxResult<isize> {
-    Ok(decode(input, 811589153, 10))
+    decrypt(input, 811589153, 10, &[1000, 2000, 3000])
 }
 
 pub fn run() {
@@ -56,4 +281,71 @@ mod tests {
         let input = &read_file_to_string("./examples/day20.txt");
         assert_eq!(part_two(input).ok(), Some(1623178306));
     }
+
+    #[test]
+    fn test_mixer() {
+        let mut mixer = CircularMixer::new(&[1, 2, -3, 3, -2, 0, 4]);
+        mixer.mix_one(0);
+        assert_eq!(mixer.to_vec(), vec![2, 1, -3, 3, -2, 0, 4]);
+        mixer.mix_one(1);
+        assert_eq!(mixer.to_vec(), vec![1, -3, 2, 3, -2, 0, 4]);
+        for index in 2..mixer.len() {
+            mixer.mix_one(index);
+        }
+        assert_eq!(mixer.to_vec(), vec![-2, 1, 2, -3, 4, 0, 3]);
+        assert_eq!(mixer.position_of(5), 5);
+        assert_eq!(mixer.get(5 + 1000), Some(4));
+
+        let mut empty = CircularMixer::new(&[]);
+        empty.mix();
+        assert_eq!(empty.get(3), None);
+        assert_eq!(empty.grove_coordinates(&[1000]), None);
+    }
+
+    fn from_zero(values: Vec<isize>) -> Vec<isize> {
+        let zero = values.iter().position(|&v| v == 0).unwrap();
+        values[zero..]
+            .iter()
+            .chain(&values[..zero])
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_rounds_and_unmix() {
+        let input = &read_file_to_string("./examples/day20.txt");
+        let values = parse_numbers(input, 811589153).unwrap();
+        let mut mixer = CircularMixer::new(&values);
+        let rounds = mixer.mix_rounds(10).collect::<Vec<Vec<isize>>>();
+        assert_eq!(
+            from_zero(rounds[0].clone()),
+            from_zero(vec![
+                0,
+                -2434767459,
+                3246356612,
+                -1623178306,
+                2434767459,
+                1623178306,
+                811589153
+            ])
+        );
+        assert_eq!(
+            mixer.grove_coordinates(&[1000, 2000, 3000]),
+            Some(vec![811589153, 2434767459, -1623178306])
+        );
+
+        for _ in 0..10 {
+            mixer.unmix();
+        }
+        assert_eq!(from_zero(mixer.to_vec()), from_zero(values));
+    }
+
+    #[test]
+    fn test_unmix_hand_crafted() {
+        let values = vec![3, -1, 0, 7, -8, 2];
+        let mut mixer = CircularMixer::new(&values);
+        mixer.mix();
+        mixer.unmix();
+        assert_eq!(from_zero(mixer.to_vec()), from_zero(values));
+    }
 }
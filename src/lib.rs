@@ -74,3 +74,48 @@ where
     let f = fs::read_to_string(path);
     f.expect("coul not open input file")
 }
+
+// Plain (P2) grayscale image with values scaled from `0..=max` to `0..=255`,
+// which every viewer handles while values can exceed the 65535 the format
+// allows, and lines wrapped at the 70 characters it allows.
+pub fn pgm(grid: &[Vec<usize>], max: usize) -> String {
+    const MAXVAL: usize = 255;
+    const LINE_LENGTH: usize = 70;
+    let height = grid.len();
+    let width = grid.first().map_or(0, |row| row.len());
+    let mut res = format!("P2\n{} {}\n{}\n", width, height, MAXVAL);
+    for row in grid {
+        let mut line = String::new();
+        for &n in row {
+            let pixel = (n * MAXVAL / max.max(1)).to_string();
+            if !line.is_empty() && line.len() + 1 + pixel.len() > LINE_LENGTH {
+                res.push_str(&line);
+                res.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&pixel);
+        }
+        res.push_str(&line);
+        res.push('\n');
+    }
+    res
+}
+
+// Small deterministic generator for the randomized tests.
+#[cfg(test)]
+pub(crate) mod test_utils {
+    pub struct XorShift(pub u64);
+
+    impl XorShift {
+        // Next number in `0..bound`.
+        pub fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+}
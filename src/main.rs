@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::path::Path;
 
 use aoc2022::{
     day01, day02, day03, day04, day05, day06, day08, day09, day10, day11, day12, day13, day14,
@@ -6,6 +7,24 @@ use aoc2022::{
 };
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // `day08 [dir]` prints the best trees and heatmaps, writing them to `dir`.
+    // `day15 <row>...` prints the number of covered positions on each row.
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if let Some((day, rows)) = args.split_first() {
+        if day == "day08" {
+            day08::run_report(rows.first().map(Path::new))?;
+            return Ok(());
+        }
+        if day == "day15" {
+            let rows = rows
+                .iter()
+                .map(|row| row.parse::<isize>())
+                .collect::<Result<Vec<isize>, _>>()?;
+            day15::run_rows(&rows);
+            return Ok(());
+        }
+    }
+
     println!("--Day 01--");
     day01::run();
     println!("--Day 02--");